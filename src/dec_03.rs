@@ -1,12 +1,14 @@
-use std::fs::read_to_string;
+mod diagnostics;
+mod grammar;
+mod lexer;
+use std::fs::File;
 
-fn load_input() -> Result<String, Box<dyn std::error::Error>> {
-    Ok(read_to_string("./inputs/dec03.txt")?)
-}
+use grammar::{Gate, Grammar, Kind, Op};
+use lexer::{DoDont, ExpressionIteratorExt, Lexer, Passthrough, Preprocessor};
 
-#[derive(Debug)]
-enum Token {
-    MUL,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Token {
+    WORD(u8),
     OPEN,
     COMMA,
     CLOSE,
@@ -16,198 +18,351 @@ enum Token {
 
 // now, we need to tokenize.
 // tokenization definition is:
-// `mul` -> MUL
+// any instruction name in the `Grammar` -> WORD
 // `(` -> OPEN_BRACKET
 // `,` -> COMMA
 // `)` -> CLOSE_BRACKET
-// `\d+` -> NUMBER
-// else -> discarded.
-fn tokenize(stream: &[u8]) -> Vec<Token> {
-    let mut tokens_out = vec![];
-
-    let mut i = 0_usize;
-    while i < stream.len() {
-        // try MUL token
-        let x = stream.get(i..i + 3);
-        if x.is_some() && x.unwrap() == b"mul" {
-            tokens_out.push(Token::MUL);
-            i += 3;
-            continue;
-        }
-        match stream[i] {
-            b'0'..=b'9' => {
-                let mut j = i + 1;
-                while j < stream.len() && (b'0'..=b'9').contains(&stream[j]) {
-                    j += 1;
-                }
-                if let Ok(string) = std::str::from_utf8(&stream[i..j]) {
-                    if let Ok(number) = string.parse() {
-                        tokens_out.push(Token::NUMBER(number));
-                        i = j;
-                        continue;
-                    }
-                }
+// `\d{1,max_digits}` -> NUMBER
+// else -> JUNK, one per run.
+// see `lexer::Lexer`, which does this lazily over any `Read`.
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expression {
+    Op(Op, Vec<u16>),
+    State(Gate),
+}
+
+/// a range of byte offsets into the scanned input
+pub(crate) type Span = std::ops::Range<usize>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Spanned<T> {
+    pub(crate) node: T,
+    pub(crate) span: Span,
+}
+
+/// Why a call that got as far as an instruction name wasn't accepted
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Reason {
+    Unexpected {
+        expected: &'static str,
+        found: Token,
+    },
+    Arity {
+        expected: usize,
+        found: usize,
+    },
+    Unterminated,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::Unexpected { expected, found } => {
+                let found = match found {
+                    Token::WORD(_) => "another instruction",
+                    Token::OPEN => "`(`",
+                    Token::COMMA => "`,`",
+                    Token::CLOSE => "`)`",
+                    Token::NUMBER(_) => "an operand",
+                    Token::JUNK => "junk",
+                };
+                write!(f, "expected {expected}, found {found}")
             }
-            b'(' => tokens_out.push(Token::OPEN),
-            b')' => tokens_out.push(Token::CLOSE),
-            b',' => tokens_out.push(Token::COMMA),
-            _ => tokens_out.push(Token::JUNK),
+            Reason::Arity { expected, found } => {
+                write!(f, "takes {expected} operand(s), given {found}")
+            }
+            Reason::Unterminated => write!(f, "the input ends before the `)`"),
         }
-        i += 1;
     }
+}
+
+/// A call that looked like an instruction, but was rejected. The span runs
+/// from the instruction name through the first byte that broke it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct NearMiss {
+    pub(crate) span: Span,
+    pub(crate) reason: Reason,
+}
+
+/// how far into `name(a,b,...)` the parser has matched, and where the name began
+enum State {
+    Start,
+    Word(u8, usize),
+    /// inside the brackets, either just opened or just past a comma
+    Operand(u8, usize, Vec<u16>),
+    /// inside the brackets, just past an operand
+    Separator(u8, usize, Vec<u16>),
+}
 
-    tokens_out
+/// Lazily matches instructions out of a token stream, yielding each accepted
+/// instruction, or each near miss, with its span. Any token that breaks the
+/// pattern starts the search over, from that token.
+pub(crate) struct Parser<'g, I> {
+    tokens: I,
+    grammar: &'g Grammar,
+    state: State,
+    /// where the last token ended
+    end: usize,
 }
 
-static DO_SYMBOL: &[u8] = "do()".as_bytes();
-static DONT_SYMBOL: &[u8] = "don't()".as_bytes();
-static MUL_SYMBOL: &[u8] = "mul".as_bytes();
+impl<'g, I: Iterator<Item = (Token, Span)>> Parser<'g, I> {
+    pub(crate) fn new(tokens: I, grammar: &'g Grammar) -> Self {
+        Self {
+            tokens,
+            grammar,
+            state: State::Start,
+            end: 0,
+        }
+    }
 
-static DO_LEN: usize = DO_SYMBOL.len();
-static DONT_LEN: usize = DONT_SYMBOL.len();
-static MUL_LEN: usize = MUL_SYMBOL.len();
+    /// just the accepted instructions, without their spans
+    pub(crate) fn accepted(self) -> impl Iterator<Item = Expression> + 'g
+    where
+        I: 'g,
+    {
+        self.filter_map(|parsed| parsed.ok().map(|expr| expr.node))
+    }
 
-fn do_dont_preproc_tokenize(stream: &[u8]) -> Vec<Token> {
-    let mut accepting = true;
-    let mut tokens_out = vec![];
+    fn finish(&self, id: u8, start: usize, args: Vec<u16>, end: usize) -> Parsed {
+        let instr = self.grammar.get(id);
+        let span = start..end;
+
+        if args.len() != instr.arity {
+            let reason = Reason::Arity {
+                expected: instr.arity,
+                found: args.len(),
+            };
+            return Err(NearMiss { span, reason });
+        }
 
-    let mut i = 0_usize;
-    while i < stream.len() {
-        if !accepting {
-            let x = stream.get(i..i + DO_LEN);
-            if x.is_some() && x.unwrap() == DO_SYMBOL {
-                accepting = true;
-                i += DO_LEN;
-                continue;
+        let node = match instr.kind {
+            Kind::Op(op) => Expression::Op(op, args),
+            Kind::State(gate) => Expression::State(gate),
+        };
+        Ok(Spanned { node, span })
+    }
+
+    fn step(&mut self, token: Token, span: Span) -> Option<Parsed> {
+        let grammar = self.grammar;
+        let arity = |id: u8| grammar.get(id).arity;
+        let unexpected = |start: usize, expected: &'static str| NearMiss {
+            span: start..span.start + 1,
+            reason: Reason::Unexpected {
+                expected,
+                found: token,
+            },
+        };
+
+        let (next, parsed) = match (std::mem::replace(&mut self.state, State::Start), token) {
+            (State::Word(id, start), Token::OPEN) => (State::Operand(id, start, vec![]), None),
+            (State::Word(_, start), _) => (State::Start, Some(Err(unexpected(start, "`(`")))),
+            (State::Operand(id, start, args), Token::CLOSE) if args.is_empty() => {
+                (State::Start, Some(self.finish(id, start, args, span.end)))
             }
-        } else {
-            // try don't() preproc
-            let x = stream.get(i..i + DONT_LEN);
-            if x.is_some() && x.unwrap() == DONT_SYMBOL {
-                accepting = false;
-                i += DONT_LEN;
-                continue;
+            (State::Operand(id, start, mut args), Token::NUMBER(x)) => {
+                args.push(x);
+                (State::Separator(id, start, args), None)
             }
-
-            // try MUL token
-            let x = stream.get(i..i + 3);
-            if x.is_some() && x.unwrap() == MUL_SYMBOL {
-                tokens_out.push(Token::MUL);
-                i += MUL_LEN;
-                continue;
+            (State::Separator(id, start, args), Token::COMMA) if args.len() < arity(id) => {
+                (State::Operand(id, start, args), None)
             }
-            match stream[i] {
-                b'0'..=b'9' => {
-                    let mut j = i + 1;
-                    while j < stream.len() && (b'0'..=b'9').contains(&stream[j]) {
-                        j += 1;
-                    }
-                    if let Ok(string) = std::str::from_utf8(&stream[i..j]) {
-                        if let Ok(number) = string.parse() {
-                            tokens_out.push(Token::NUMBER(number));
-                            i = j;
-                            continue;
-                        }
-                    }
-                }
-                b'(' => tokens_out.push(Token::OPEN),
-                b')' => tokens_out.push(Token::CLOSE),
-                b',' => tokens_out.push(Token::COMMA),
-                _ => tokens_out.push(Token::JUNK),
+            (State::Separator(id, start, args), Token::CLOSE) => {
+                (State::Start, Some(self.finish(id, start, args, span.end)))
             }
-        }
+            (State::Operand(_, start, args), _) => {
+                let expected = if args.is_empty() {
+                    "an operand or `)`"
+                } else {
+                    "an operand"
+                };
+                (State::Start, Some(Err(unexpected(start, expected))))
+            }
+            (State::Separator(id, start, args), _) => {
+                let expected = if args.len() < arity(id) {
+                    "`,` or `)`"
+                } else {
+                    "`)`"
+                };
+                (State::Start, Some(Err(unexpected(start, expected))))
+            }
+            _ => (State::Start, None),
+        };
 
-        i += 1;
-    }
+        // whatever the outcome, a name can always start the next instruction
+        self.state = match token {
+            Token::WORD(id) => State::Word(id, span.start),
+            _ => next,
+        };
+        self.end = span.end;
 
-    tokens_out
+        parsed
+    }
 }
 
-enum Expression {
-    MUL(u16, u16),
-}
+pub(crate) type Parsed = Result<Spanned<Expression>, NearMiss>;
 
-fn parse(tokens: Vec<Token>) -> Vec<Expression> {
-    let mut expr_out = vec![];
+impl<I: Iterator<Item = (Token, Span)>> Iterator for Parser<'_, I> {
+    type Item = Parsed;
 
-    let mut i = 0;
-    while i < tokens.len() {
-        let num_a;
-        let num_b;
-        while i < tokens.len() && !matches!(tokens[i], Token::MUL) {
-            i += 1;
-        }
-        i += 1;
-        if i >= tokens.len() || !matches!(tokens[i], Token::OPEN) {
-            continue;
-        }
-        i += 1;
-        if i >= tokens.len() {
-            continue;
-        }
-        match tokens[i] {
-            Token::NUMBER(a) => num_a = a,
-            _ => continue,
-        }
-        i += 1;
-        if i >= tokens.len() || !matches!(tokens[i], Token::COMMA) {
-            continue;
-        }
-        i += 1;
-        if i >= tokens.len() {
-            continue;
-        }
-        match tokens[i] {
-            Token::NUMBER(b) => num_b = b,
-            _ => continue,
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some((token, span)) = self.tokens.next() else {
+                return match std::mem::replace(&mut self.state, State::Start) {
+                    State::Word(_, start)
+                    | State::Operand(_, start, _)
+                    | State::Separator(_, start, _) => Some(Err(NearMiss {
+                        span: start..self.end,
+                        reason: Reason::Unterminated,
+                    })),
+                    _ => None,
+                };
+            };
+
+            if let Some(parsed) = self.step(token, span) {
+                return Some(parsed);
+            }
         }
-        i += 1;
-        if i >= tokens.len() || !matches!(tokens[i], Token::CLOSE) {
+    }
+}
+
+/// How a run of arithmetic instructions combines
+#[derive(Clone, Copy)]
+pub(crate) enum Semantics {
+    /// each instruction folds its own operands, and the results are summed
+    SumOfProducts,
+    /// each instruction folds its operands into one running total, from 0
+    Accumulator,
+}
+
+/// `None` if any step overflows or divides by zero. State instructions are
+/// skipped; gating them is up to a `Preprocessor`.
+fn evaluate(exprs: impl IntoIterator<Item = Expression>, semantics: Semantics) -> Option<i64> {
+    let mut total = 0_i64;
+    for expr in exprs {
+        let Expression::Op(op, args) = expr else {
             continue;
-        }
+        };
+        let mut args = args.into_iter().map(|x| x as i64);
 
-        expr_out.push(Expression::MUL(num_a, num_b))
+        total = match semantics {
+            Semantics::SumOfProducts => {
+                let first = args.next()?;
+                let value = args.try_fold(first, |acc, x| op.apply(acc, x))?;
+                total.checked_add(value)?
+            }
+            Semantics::Accumulator => args.try_fold(total, |acc, x| op.apply(acc, x))?,
+        };
     }
 
-    expr_out
+    Some(total)
 }
 
-fn evaluate_sum(exprs: Vec<Expression>) -> u32 {
-    let mut sum = 0;
-    for expr in exprs {
-        match expr {
-            Expression::MUL(a, b) => sum += a as u32 * b as u32,
-        }
+fn scan_file(
+    grammar: &Grammar,
+    preprocessor: impl Preprocessor,
+    semantics: Semantics,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let mut lexer = Lexer::new(File::open("./inputs/dec03.txt")?, grammar);
+    let exprs = Parser::new(lexer.by_ref(), grammar)
+        .accepted()
+        .preprocess(preprocessor);
+    let sum = evaluate(exprs, semantics).ok_or("the sum overflowed")?;
+
+    match lexer.take_error() {
+        Some(e) => Err(e.into()),
+        None => Ok(sum),
     }
+}
 
-    sum
+/// Prints the input with accepted instructions highlighted and disabled
+/// regions dimmed, then lists every instruction and near miss
+pub fn diagnose() -> Result<(), Box<dyn std::error::Error>> {
+    let input = std::fs::read_to_string("./inputs/dec03.txt")?;
+    let report = diagnostics::Report::new(input.as_bytes(), &Grammar::aoc());
+
+    println!("{}", report.highlight(&input));
+    println!("{}", report.listing(&input));
+
+    Ok(())
 }
 
-pub fn puzzle1() -> Result<u32, Box<dyn std::error::Error>> {
-    let input_stream = load_input()?;
-    let tokens = tokenize(input_stream.as_bytes());
-    let expressions = parse(tokens);
-    Ok(evaluate_sum(expressions))
+/// Prints the input's total with the rest of the arithmetic switched on, and
+/// do/don't gating, under both semantics
+pub fn arithmetic() -> Result<(), Box<dyn std::error::Error>> {
+    let grammar = Grammar::new(grammar::ARITHMETIC, 3);
+    let sum = scan_file(&grammar, DoDont::default(), Semantics::SumOfProducts)?;
+    let total = scan_file(&grammar, DoDont::default(), Semantics::Accumulator)?;
+
+    println!("sum of results: {sum}");
+    println!("running total:  {total}");
+
+    Ok(())
 }
-pub fn puzzle2() -> Result<u32, Box<dyn std::error::Error>> {
-    let input_stream = load_input()?;
-    let tokens = do_dont_preproc_tokenize(input_stream.as_bytes());
-    let expressions = parse(tokens);
-    Ok(evaluate_sum(expressions))
+
+pub fn puzzle1() -> Result<i64, Box<dyn std::error::Error>> {
+    scan_file(&Grammar::aoc(), Passthrough, Semantics::SumOfProducts)
+}
+pub fn puzzle2() -> Result<i64, Box<dyn std::error::Error>> {
+    scan_file(&Grammar::aoc(), DoDont::default(), Semantics::SumOfProducts)
 }
 
 #[test]
 fn test_parser() {
+    let grammar = Grammar::aoc();
     let test_input = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
-    let tokens = tokenize(test_input.as_bytes());
-    let expressions = parse(tokens);
-    assert_eq!(evaluate_sum(expressions), 161);
+    let tokens = Lexer::new(test_input.as_bytes(), &grammar);
+    let expressions = Parser::new(tokens, &grammar).accepted();
+    assert_eq!(evaluate(expressions, Semantics::SumOfProducts), Some(161));
 }
 
 #[test]
 fn test_parse_do_dont() {
+    let grammar = Grammar::aoc();
     let test_input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
-    let tokens = do_dont_preproc_tokenize(test_input.as_bytes());
-    let expressions = parse(tokens);
-    assert_eq!(evaluate_sum(expressions), 48);
+    let tokens = Lexer::new(test_input.as_bytes(), &grammar);
+    let expressions = Parser::new(tokens, &grammar)
+        .accepted()
+        .preprocess(DoDont::default());
+    assert_eq!(evaluate(expressions, Semantics::SumOfProducts), Some(48));
+}
+
+#[test]
+fn test_grammar() {
+    let grammar = Grammar::new(grammar::ARITHMETIC, 3);
+    let exprs = |input: &str| {
+        let tokens = Lexer::new(input.as_bytes(), &grammar);
+        Parser::new(tokens, &grammar).accepted().collect::<Vec<_>>()
+    };
+
+    // arity is checked both ways, and operands are at most 3 digits
+    assert_eq!(
+        exprs("add(1,2,3)sub(4)mul(1234,2)div(8,2)do(1)don't()"),
+        vec![
+            Expression::Op(Op::Div, vec![8, 2]),
+            Expression::State(Gate::Disable)
+        ]
+    );
+
+    // a `don't()` inside a half-finished call breaks it up
+    let gated = |input: &str| {
+        let tokens = Lexer::new(input.as_bytes(), &grammar);
+        let exprs = Parser::new(tokens, &grammar)
+            .accepted()
+            .preprocess(DoDont::default());
+        evaluate(exprs, Semantics::SumOfProducts)
+    };
+    assert_eq!(gated("mul(2,don't()mul(1,1)do()3)"), Some(0));
+
+    let both = |input: &str| {
+        let tokens = Lexer::new(input.as_bytes(), &grammar);
+        let exprs = Parser::new(tokens, &grammar).accepted().collect::<Vec<_>>();
+        let sum = evaluate(exprs.clone(), Semantics::SumOfProducts);
+        (sum, evaluate(exprs, Semantics::Accumulator))
+    };
+    assert_eq!(
+        both("add(2,3)mul(4,5)sub(1,9)div(7,2)"),
+        (Some(20), Some(6))
+    );
+    assert_eq!(both("div(1,0)"), (None, None));
 }
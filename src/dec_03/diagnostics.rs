@@ -0,0 +1,183 @@
+use super::grammar::{Gate, Grammar};
+use super::lexer::Lexer;
+use super::{Expression, NearMiss, Parser, Span, Spanned};
+
+static ACCEPTED: &str = "\x1b[1;32m";
+static GATE: &str = "\x1b[36m";
+static REJECTED: &str = "\x1b[31m";
+static DISABLED: &str = "\x1b[2m";
+static RESET: &str = "\x1b[0m";
+
+/// Everything the parser made of an input, in order
+pub(crate) struct Report {
+    pub(crate) accepted: Vec<Spanned<Expression>>,
+    pub(crate) near_misses: Vec<NearMiss>,
+    /// from the end of each `don't()` that switched things off to the start
+    /// of the `do()` that switched them back on
+    pub(crate) disabled: Vec<Span>,
+}
+
+impl Report {
+    pub(crate) fn new(input: &[u8], grammar: &Grammar) -> Self {
+        let mut accepted = vec![];
+        let mut near_misses = vec![];
+        let mut disabled = vec![];
+        let mut disabled_from = None;
+
+        for parsed in Parser::new(Lexer::new(input, grammar), grammar) {
+            match parsed {
+                Ok(expr) => {
+                    match (&expr.node, disabled_from) {
+                        (Expression::State(Gate::Disable), None) => {
+                            disabled_from = Some(expr.span.end)
+                        }
+                        (Expression::State(Gate::Enable), Some(from)) => {
+                            disabled.push(from..expr.span.start);
+                            disabled_from = None;
+                        }
+                        _ => {}
+                    }
+                    accepted.push(expr);
+                }
+                Err(near_miss) => near_misses.push(near_miss),
+            }
+        }
+        if let Some(from) = disabled_from {
+            disabled.push(from..input.len());
+        }
+
+        Self {
+            accepted,
+            near_misses,
+            disabled,
+        }
+    }
+
+    pub(crate) fn is_disabled(&self, span: &Span) -> bool {
+        self.disabled
+            .iter()
+            .any(|region| region.start <= span.start && span.end <= region.end)
+    }
+
+    /// `input` with ANSI colours: accepted arithmetic in bold green, state
+    /// instructions in cyan, near misses in red, and disabled regions dimmed
+    pub(crate) fn highlight(&self, input: &str) -> String {
+        let mut styles = vec![""; input.len()];
+        let mut paint = |span: &Span, style: &'static str| {
+            styles[span.clone()].iter_mut().for_each(|s| *s = style);
+        };
+
+        for near_miss in &self.near_misses {
+            paint(&near_miss.span, REJECTED);
+        }
+        for expr in &self.accepted {
+            match expr.node {
+                Expression::Op(_, _) => paint(&expr.span, ACCEPTED),
+                Expression::State(_) => paint(&expr.span, GATE),
+            }
+        }
+        for region in &self.disabled {
+            paint(region, DISABLED);
+        }
+
+        let mut out = String::with_capacity(input.len());
+        let mut current = "";
+        for (i, c) in input.char_indices() {
+            if styles[i] != current {
+                out.push_str(RESET);
+                out.push_str(styles[i]);
+                current = styles[i];
+            }
+            out.push(c);
+        }
+        out.push_str(RESET);
+
+        out
+    }
+
+    /// one line per instruction and near miss, in input order
+    pub(crate) fn listing(&self, input: &str) -> String {
+        let text = |span: &Span| String::from_utf8_lossy(&input.as_bytes()[span.clone()]);
+
+        let accepted = self.accepted.iter().map(|expr| {
+            let status = if self.is_disabled(&expr.span) {
+                "off"
+            } else {
+                "ok"
+            };
+            let line = format!(
+                "{status:<4} {:>12}  {}",
+                format_span(&expr.span),
+                text(&expr.span)
+            );
+            (expr.span.start, line)
+        });
+        let near_misses = self.near_misses.iter().map(|near_miss| {
+            let line = format!(
+                "miss {:>12}  {}  <- {}",
+                format_span(&near_miss.span),
+                text(&near_miss.span),
+                near_miss.reason
+            );
+            (near_miss.span.start, line)
+        });
+
+        let mut lines = accepted.chain(near_misses).collect::<Vec<_>>();
+        lines.sort_by_key(|(start, _)| *start);
+
+        lines
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn format_span(span: &Span) -> String {
+    format!("{}..{}", span.start, span.end)
+}
+
+#[test]
+fn test_report() {
+    let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))mul(1";
+    let report = Report::new(input.as_bytes(), &Grammar::aoc());
+
+    assert_eq!(
+        report.listing(input),
+        [
+            "ok           1..9  mul(2,4)",
+            "miss       10..14  mul[  <- expected `(`, found junk",
+            "ok         20..27  don't()",
+            "off        28..36  mul(5,5)",
+            "miss       37..47  mul(32,64]  <- expected `)`, found junk",
+            "off        48..57  mul(11,8)",
+            "ok         59..63  do()",
+            "ok         64..72  mul(8,5)",
+            "miss       73..78  mul(1  <- the input ends before the `)`",
+        ]
+        .join("\n")
+    );
+    assert_eq!(report.disabled, vec![27..59]);
+
+    let highlighted = report.highlight(input);
+    assert!(highlighted.contains(&format!("{ACCEPTED}mul(2,4){RESET}")));
+    assert!(highlighted.contains(&format!("{GATE}do(){RESET}")));
+    assert!(highlighted.contains(&format!("{REJECTED}mul(1{RESET}")));
+    assert!(highlighted.contains(&format!(
+        "{DISABLED}_mul(5,5)+mul(32,64](mul(11,8)un{RESET}"
+    )));
+
+    // a name that isn't followed straight away by `(` is a near miss too
+    let input = "mul (3,7)domul(1,2)mul";
+    let report = Report::new(input.as_bytes(), &Grammar::aoc());
+    assert_eq!(
+        report.listing(input),
+        [
+            "miss         0..4  mul   <- expected `(`, found junk",
+            "miss        9..12  dom  <- expected `(`, found another instruction",
+            "ok         11..19  mul(1,2)",
+            "miss       19..22  mul  <- the input ends before the `)`",
+        ]
+        .join("\n")
+    );
+}
@@ -0,0 +1,131 @@
+/// An arithmetic instruction, folded left over its operands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    /// `None` on overflow or division by zero
+    pub(crate) fn apply(&self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Op::Add => a.checked_add(b),
+            Op::Sub => a.checked_sub(b),
+            Op::Mul => a.checked_mul(b),
+            Op::Div => a.checked_div(b),
+        }
+    }
+}
+
+/// Whether later instructions count
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Gate {
+    Enable,
+    Disable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Kind {
+    Op(Op),
+    State(Gate),
+}
+
+/// `name(a,b,...)` with exactly `arity` operands
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Instruction {
+    pub(crate) name: &'static str,
+    pub(crate) arity: usize,
+    pub(crate) kind: Kind,
+}
+
+impl Instruction {
+    pub(crate) const fn op(name: &'static str, arity: usize, op: Op) -> Self {
+        Self {
+            name,
+            arity,
+            kind: Kind::Op(op),
+        }
+    }
+
+    pub(crate) const fn state(name: &'static str, gate: Gate) -> Self {
+        Self {
+            name,
+            arity: 0,
+            kind: Kind::State(gate),
+        }
+    }
+}
+
+/// The puzzle's own instruction set
+pub(crate) static AOC: &[Instruction] = &[
+    Instruction::op("mul", 2, Op::Mul),
+    Instruction::state("do", Gate::Enable),
+    Instruction::state("don't", Gate::Disable),
+];
+
+/// Everything in `AOC`, plus the rest of the arithmetic
+pub(crate) static ARITHMETIC: &[Instruction] = &[
+    Instruction::op("add", 2, Op::Add),
+    Instruction::op("sub", 2, Op::Sub),
+    Instruction::op("mul", 2, Op::Mul),
+    Instruction::op("div", 2, Op::Div),
+    Instruction::state("do", Gate::Enable),
+    Instruction::state("don't", Gate::Disable),
+];
+
+/// The instructions the scanner recognises, and how long an operand may be
+pub(crate) struct Grammar {
+    instructions: Vec<Instruction>,
+    pub(crate) max_digits: usize,
+}
+
+impl Grammar {
+    /// Panics if a name is repeated, if there are more than 256 instructions,
+    /// or if operands may be longer than the 4 digits a `NUMBER` always holds
+    pub(crate) fn new(instructions: &[Instruction], max_digits: usize) -> Self {
+        assert!(instructions.len() <= u8::MAX as usize + 1);
+        assert!(
+            max_digits <= 4,
+            "{max_digits} digit operands could overflow a `NUMBER`"
+        );
+        for (i, instr) in instructions.iter().enumerate() {
+            assert!(
+                !instructions[..i]
+                    .iter()
+                    .any(|other| other.name == instr.name),
+                "`{}` is declared twice",
+                instr.name
+            );
+        }
+
+        Self {
+            instructions: instructions.to_vec(),
+            max_digits,
+        }
+    }
+
+    /// `AOC` with the puzzle's 1-3 digit operands
+    pub(crate) fn aoc() -> Self {
+        Self::new(AOC, 3)
+    }
+
+    pub(crate) fn get(&self, id: u8) -> &Instruction {
+        &self.instructions[id as usize]
+    }
+
+    /// every instruction and its id, longest name first, so that `don't`
+    /// is tried before `do`
+    pub(crate) fn by_length(&self) -> Vec<(u8, &'static str)> {
+        let mut words = self
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(id, instr)| (id as u8, instr.name))
+            .collect::<Vec<_>>();
+        words.sort_by_key(|(_, name)| std::cmp::Reverse(name.len()));
+
+        words
+    }
+}
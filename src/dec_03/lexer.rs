@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::io::{BufReader, Bytes, Read};
+
+use super::grammar::{Gate, Grammar};
+use super::{Expression, Span, Token};
+
+/// Yields tokens lazily from any `Read`, holding no more than the longest
+/// instruction name in memory, so arbitrarily large dumps scan in constant
+/// space. Runs of bytes that can't start a token collapse into a single `JUNK`.
+/// Every token comes with the byte range it was read from.
+pub(crate) struct Lexer<R: Read> {
+    source: Bytes<BufReader<R>>,
+    window: VecDeque<u8>,
+    error: Option<std::io::Error>,
+    words: Vec<(u8, &'static str)>,
+    max_digits: usize,
+    /// how many bytes have been consumed
+    offset: usize,
+}
+
+impl<R: Read> Lexer<R> {
+    pub(crate) fn new(reader: R, grammar: &Grammar) -> Self {
+        let words = grammar.by_length();
+        let longest = words.first().map_or(0, |(_, name)| name.len());
+
+        Self {
+            source: BufReader::new(reader).bytes(),
+            window: VecDeque::with_capacity(longest),
+            error: None,
+            words,
+            max_digits: grammar.max_digits,
+            offset: 0,
+        }
+    }
+
+    /// the read error that ended the stream early, if there was one
+    pub(crate) fn take_error(&mut self) -> Option<std::io::Error> {
+        self.error.take()
+    }
+
+    /// make sure `n` bytes are buffered, unless the source runs out first
+    fn fill(&mut self, n: usize) {
+        while self.window.len() < n && self.error.is_none() {
+            match self.source.next() {
+                Some(Ok(byte)) => self.window.push_back(byte),
+                Some(Err(e)) => self.error = Some(e),
+                None => break,
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.fill(1);
+        self.window.front().copied()
+    }
+
+    fn starts_with(&mut self, symbol: &[u8]) -> bool {
+        self.fill(symbol.len());
+        self.window.iter().take(symbol.len()).eq(symbol.iter())
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.window.drain(..n);
+        self.offset += n;
+    }
+
+    fn starts_token(&self, byte: u8) -> bool {
+        matches!(byte, b'0'..=b'9' | b'(' | b')' | b',')
+            || self
+                .words
+                .iter()
+                .any(|(_, name)| name.as_bytes()[0] == byte)
+    }
+}
+
+impl<R: Read> Iterator for Lexer<R> {
+    type Item = (Token, Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.peek()?;
+        let start = self.offset;
+
+        for i in 0..self.words.len() {
+            let (id, name) = self.words[i];
+            if self.starts_with(name.as_bytes()) {
+                self.advance(name.len());
+                return Some((Token::WORD(id), start..self.offset));
+            }
+        }
+
+        self.advance(1);
+        let token = match first {
+            b'0'..=b'9' => {
+                // consume the whole run even once it's too long, so `1234`
+                // is one `JUNK` rather than `123` followed by `4`
+                let mut number = (first - b'0') as u16;
+                let mut digits = 1;
+                while let Some(digit @ b'0'..=b'9') = self.peek() {
+                    number = number
+                        .saturating_mul(10)
+                        .saturating_add((digit - b'0') as u16);
+                    digits += 1;
+                    self.advance(1);
+                }
+
+                if digits <= self.max_digits {
+                    Token::NUMBER(number)
+                } else {
+                    Token::JUNK
+                }
+            }
+            b'(' => Token::OPEN,
+            b')' => Token::CLOSE,
+            b',' => Token::COMMA,
+            _ => {
+                while self.peek().is_some_and(|byte| !self.starts_token(byte)) {
+                    self.advance(1);
+                }
+
+                Token::JUNK
+            }
+        };
+
+        Some((token, start..self.offset))
+    }
+}
+
+/// Decides, instruction by instruction, what gets evaluated
+pub(crate) trait Preprocessor {
+    fn accept(&mut self, expr: &Expression) -> bool;
+}
+
+/// Lets everything through
+pub(crate) struct Passthrough;
+
+impl Preprocessor for Passthrough {
+    fn accept(&mut self, _: &Expression) -> bool {
+        true
+    }
+}
+
+/// Drops every instruction between a disabling state instruction (`don't()`)
+/// and the next enabling one (`do()`)
+#[derive(Default)]
+pub(crate) struct DoDont {
+    disabled: bool,
+}
+
+impl Preprocessor for DoDont {
+    fn accept(&mut self, expr: &Expression) -> bool {
+        match expr {
+            Expression::State(Gate::Enable) => self.disabled = false,
+            Expression::State(Gate::Disable) => self.disabled = true,
+            _ => {}
+        }
+
+        !self.disabled
+    }
+}
+
+// Convenience method to run parsed instructions through a preprocessor
+pub(crate) trait ExpressionIteratorExt: Iterator<Item = Expression> {
+    fn preprocess<P: Preprocessor>(self, mut preprocessor: P) -> impl Iterator<Item = Expression>
+    where
+        Self: Sized,
+    {
+        self.filter(move |expr| preprocessor.accept(expr))
+    }
+}
+
+impl<I: Iterator<Item = Expression>> ExpressionIteratorExt for I {}
+
+#[test]
+fn test_lexer_streams() {
+    /// hands out one byte per `read`, so every keyword straddles a refill
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((byte, rest)), Some(slot)) => {
+                    *slot = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    let grammar = Grammar::aoc();
+    let input = b"%mul(12,345)?!don't()do()99999(";
+    let (tokens, spans): (Vec<_>, Vec<_>) = Lexer::new(Trickle(input), &grammar).unzip();
+
+    let [mul, _do, dont] = [0, 1, 2];
+    assert_eq!(
+        tokens,
+        vec![
+            Token::JUNK,
+            Token::WORD(mul),
+            Token::OPEN,
+            Token::NUMBER(12),
+            Token::COMMA,
+            Token::NUMBER(345),
+            Token::CLOSE,
+            Token::JUNK,
+            Token::WORD(dont),
+            Token::OPEN,
+            Token::CLOSE,
+            Token::WORD(_do),
+            Token::OPEN,
+            Token::CLOSE,
+            Token::JUNK,
+            Token::OPEN,
+        ]
+    );
+    assert_eq!(spans[1..7], [1..4, 4..5, 5..7, 7..8, 8..11, 11..12]);
+    assert_eq!(spans[7], 12..14);
+    assert_eq!(spans[14..], [25..30, 30..31]);
+}
+
+#[test]
+fn test_do_dont_gate() {
+    use super::grammar::Op;
+
+    let mul = |a, b| Expression::Op(Op::Mul, vec![a, b]);
+    let [on, off] = [Gate::Enable, Gate::Disable].map(Expression::State);
+    let exprs = vec![
+        mul(1, 1),
+        off.clone(),
+        mul(2, 2),
+        off.clone(),
+        mul(3, 3),
+        on.clone(),
+        mul(4, 4),
+        on.clone(),
+        mul(5, 5),
+    ];
+
+    // repeated `don't()`s and `do()`s are idempotent, and `don't()` drops itself
+    let gated = exprs.clone().into_iter().preprocess(DoDont::default());
+    assert_eq!(
+        gated.collect::<Vec<_>>(),
+        vec![mul(1, 1), on.clone(), mul(4, 4), on, mul(5, 5)]
+    );
+
+    let passed = exprs.clone().into_iter().preprocess(Passthrough);
+    assert_eq!(passed.collect::<Vec<_>>(), exprs);
+}
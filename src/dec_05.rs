@@ -12,109 +12,551 @@ pub fn puzzle2() -> Result<u32, Box<dyn std::error::Error>> {
     handle_puzzle2(std::fs::read_to_string("./inputs/dec05.txt")?.as_str())
 }
 
+/// Prints each update that breaks the rules, which rules and the least work
+/// to repair it, and the order it should be in, and for those that don't,
+/// whether that's the only order the rules allow
+pub fn audit() -> Result<(), Box<dyn std::error::Error>> {
+    let (updates, constraints) = load_puzzle(&std::fs::read_to_string("./inputs/dec05.txt")?)?;
+
+    for update in &updates {
+        println!("{update:?}");
+        if constraints.is_correct(update) {
+            match constraints.count_orderings(update) {
+                Some(1) => println!("\tcorrect, and the only order the rules allow"),
+                Some(n) => println!("\tcorrect, and one of {n} orders the rules allow"),
+                None => println!("\tcorrect"),
+            }
+            for (a, b) in constraints
+                .unconstrained_pairs(update)
+                .into_iter()
+                .flatten()
+            {
+                println!("\tno rule orders {a} and {b}");
+            }
+            // the update itself comes first
+            for other in constraints
+                .orderings(update)
+                .into_iter()
+                .flatten()
+                .skip(1)
+                .take(3)
+            {
+                println!("\talso allowed: {other:?}");
+            }
+            continue;
+        }
+
+        for violation in constraints.violations(update) {
+            println!("\t{violation}");
+        }
+        if let Some(Repair { moves, swaps }) = constraints.repair_cost(update) {
+            println!("\trepair: {moves} moves, or {swaps} swaps");
+        }
+        match constraints.reorder(update, Mode::Strict) {
+            Ok(order) => println!("\treordered: {order:?}"),
+            Err(cycle) => {
+                println!("\t{cycle}");
+                let order = constraints.reorder(update, Mode::BestEffort)?;
+                println!("\tbest effort: {order:?}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A set of pages, one bit per possible `u8`
+#[derive(Clone, Copy, Default)]
+struct PageSet([u64; 4]);
+
+impl PageSet {
+    fn insert(&mut self, page: u8) {
+        self.0[(page >> 6) as usize] |= 1 << (page & 63);
+    }
+
+    fn contains(&self, page: u8) -> bool {
+        self.0[(page >> 6) as usize] & (1 << (page & 63)) != 0
+    }
+}
+
 pub struct Constraints {
     top: TopologicalSort,
-    constraints: Vec<(u8, u8)>,
+    /// `before[a]` holds every `b` with a rule `a|b`
+    before: Vec<PageSet>,
 }
 
 impl Constraints {
     pub fn new(constraints: Vec<(u8, u8)>) -> Self {
+        let mut before = vec![PageSet::default(); 256];
+        for (prior, posterior) in &constraints {
+            before[*prior as usize].insert(*posterior);
+        }
+
         Self {
-            top: TopologicalSort::from_constraints(constraints.clone()),
-            constraints,
+            top: TopologicalSort::from_constraints(constraints),
+            before,
         }
     }
 
+    /// whether there's a rule `prior|posterior`
+    pub fn requires(self: &Self, prior: u8, posterior: u8) -> bool {
+        self.before[prior as usize].contains(posterior)
+    }
+
+    /// an update is correct when no page has a rule saying it belongs
+    /// before one that's earlier in the update
     pub fn is_correct(self: &Self, update: &Vec<u8>) -> bool {
-        for (prior, posterior) in &self.constraints {
-            let prior_index = update.iter().position(|n| n == prior);
-            let posterior_index = update.iter().position(|n| n == posterior);
-
-            if let Some(prior_index) = prior_index {
-                if let Some(posterior_index) = posterior_index {
-                    if prior_index >= posterior_index {
-                        return false;
-                    }
+        update.iter().enumerate().all(|(j, later)| {
+            update[..j]
+                .iter()
+                .all(|earlier| !self.requires(*later, *earlier))
+        })
+    }
+
+    /// whether the rules say which way round every pair in `update` goes,
+    /// without going round in a cycle, which is what `sort_by_rules` needs
+    pub fn is_total(self: &Self, update: &[u8]) -> bool {
+        let every_pair = update.iter().enumerate().all(|(j, b)| {
+            update[..j]
+                .iter()
+                .all(|a| self.requires(*a, *b) != self.requires(*b, *a))
+        });
+
+        // with a rule for every pair, there's no cycle exactly when each page
+        // goes before a different number of the others. Every pair having a
+        // rule also means no page repeats, so the counts fit in a `u8`.
+        let mut counts = PageSet::default();
+        every_pair
+            && update.iter().all(|a| {
+                let later = update.iter().filter(|b| self.requires(*a, **b)).count() as u8;
+                let fresh = !counts.contains(later);
+                counts.insert(later);
+                fresh
+            })
+    }
+
+    /// In place, with no allocation. Only meaningful when `is_total`; use
+    /// `reorder` for anything else.
+    pub fn sort_by_rules(self: &Self, update: &mut [u8]) {
+        update.sort_unstable_by(|a, b| {
+            if self.requires(*a, *b) {
+                std::cmp::Ordering::Less
+            } else if self.requires(*b, *a) {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        });
+    }
+
+    /// `masks[i]` has bit `j` set when a rule puts `update[j]` directly before
+    /// `update[i]`, or `None` for updates longer than `MAX_MASKED_LEN`
+    fn predecessor_masks(self: &Self, update: &[u8]) -> Option<Vec<u64>> {
+        if update.len() > MAX_MASKED_LEN {
+            return None;
+        }
+
+        let masks = update
+            .iter()
+            .map(|b| {
+                update
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, a)| self.requires(**a, *b))
+                    .fold(0, |mask, (j, _)| mask | 1 << j)
+            })
+            .collect();
+
+        Some(masks)
+    }
+
+    /// `predecessor_masks`, closed Warshall style: bit `j` of `masks[i]` is
+    /// set when some chain of rules puts `update[j]` before `update[i]`
+    fn closure_masks(self: &Self, update: &[u8]) -> Option<Vec<u64>> {
+        let mut reach = self.predecessor_masks(update)?;
+        for k in 0..update.len() {
+            for i in 0..update.len() {
+                if reach[i] & 1 << k != 0 {
+                    reach[i] |= reach[k];
                 }
             }
         }
 
-        true
+        Some(reach)
     }
 
-    pub fn reorder(self: &Self, update: &mut Vec<u8>) -> Vec<u8> {
-        self.top.sort(update)
+    /// How many orderings of `update` satisfy every rule, by dynamic
+    /// programming over which pages have been placed so far. `None` for
+    /// updates longer than `MAX_COUNTED_LEN`, where the table gets too big.
+    pub fn count_orderings(self: &Self, update: &[u8]) -> Option<u64> {
+        if update.len() > MAX_COUNTED_LEN {
+            return None;
+        }
+
+        let preds = self.predecessor_masks(update)?;
+        let full = (1_usize << update.len()) - 1;
+        let mut ways = vec![0_u64; full + 1];
+        ways[0] = 1;
+
+        for placed in 0..full {
+            if ways[placed] == 0 {
+                continue;
+            }
+            for (i, pred) in preds.iter().enumerate() {
+                if placed & 1 << i == 0 && *pred as usize & !placed == 0 {
+                    ways[placed | 1 << i] += ways[placed];
+                }
+            }
+        }
+
+        Some(ways[full])
+    }
+
+    /// Every ordering of `update` that satisfies the rules, lazily, with pages
+    /// that are free to go next tried in the order `update` gives them.
+    /// `None` for updates longer than `MAX_MASKED_LEN`.
+    pub fn orderings(self: &Self, update: &[u8]) -> Option<Orderings> {
+        Some(Orderings {
+            pages: update.to_vec(),
+            preds: self.predecessor_masks(update)?,
+            prefix: vec![],
+            placed: 0,
+            cursors: vec![0; update.len() + 1],
+            done: false,
+        })
     }
+
+    /// Pairs from `update` that no chain of rules puts in either order, so
+    /// its ordering is unique exactly when this is empty (and there's no cycle).
+    /// `None` for updates longer than `MAX_MASKED_LEN`.
+    pub fn unconstrained_pairs(self: &Self, update: &[u8]) -> Option<Vec<(u8, u8)>> {
+        let reach = self.closure_masks(update)?;
+
+        let mut pairs = vec![];
+        for j in 0..update.len() {
+            for i in 0..j {
+                if reach[i] & 1 << j == 0 && reach[j] & 1 << i == 0 {
+                    pairs.push((update[i], update[j]));
+                }
+            }
+        }
+
+        Some(pairs)
+    }
+
+    /// Every rule `update` breaks, in the order the offending later page
+    /// appears. Empty exactly when `is_correct`.
+    pub fn violations(self: &Self, update: &[u8]) -> Vec<Violation> {
+        let mut violations = vec![];
+        for (prior_at, prior) in update.iter().enumerate() {
+            for (posterior_at, posterior) in update[..prior_at].iter().enumerate() {
+                if self.requires(*prior, *posterior) {
+                    violations.push(Violation {
+                        prior: *prior,
+                        posterior: *posterior,
+                        prior_at,
+                        posterior_at,
+                    });
+                }
+            }
+        }
+
+        violations
+    }
+
+    /// The least work that makes `update` correct, or `None` if no order
+    /// can be (the rules form a cycle), or if the rules aren't `is_total`
+    /// and the update is longer than `MAX_COUNTED_LEN`.
+    ///
+    /// Chains of rules count, so the pages that can stay put are those with
+    /// no pair the rules force the other way round.
+    pub fn repair_cost(self: &Self, update: &[u8]) -> Option<Repair> {
+        if self.is_total(update) {
+            return Some(self.repair_cost_total(update));
+        }
+        if update.len() > MAX_COUNTED_LEN {
+            return None;
+        }
+
+        let reach = self.closure_masks(update)?;
+        if reach.iter().enumerate().any(|(i, mask)| mask & 1 << i != 0) {
+            return None;
+        }
+
+        // `conflicts[i]` has bit `j` set when `i` and `j` can't both stay put
+        let conflicts = (0..update.len())
+            .map(|i| {
+                let forced_earlier = reach[i] & !((2 << i) - 1);
+                let forced_later = (0..i)
+                    .filter(|j| reach[*j] & 1 << i != 0)
+                    .fold(0, |mask, j| mask | 1 << j);
+                (forced_earlier | forced_later) as usize
+            })
+            .collect::<Vec<_>>();
+
+        let full = (1_usize << update.len()) - 1;
+        let mut can_stay = vec![true; full + 1];
+        let mut stay = 0;
+        let mut swaps = vec![usize::MAX; full + 1];
+        swaps[0] = 0;
+
+        for placed in 0..=full {
+            if placed != 0 {
+                let lowest = placed.trailing_zeros() as usize;
+                let rest = placed & (placed - 1);
+                can_stay[placed] = can_stay[rest] && conflicts[lowest] & rest == 0;
+                if can_stay[placed] {
+                    stay = stay.max(placed.count_ones() as usize);
+                }
+            }
+
+            // placing `i` next swaps it past every unplaced page ahead of it
+            if swaps[placed] == usize::MAX {
+                continue;
+            }
+            for i in 0..update.len() {
+                if placed & 1 << i == 0 && reach[i] as usize & !placed == 0 {
+                    let passed = (!placed & ((1 << i) - 1)).count_ones() as usize;
+                    let cost = swaps[placed] + passed;
+                    let next = &mut swaps[placed | 1 << i];
+                    *next = (*next).min(cost);
+                }
+            }
+        }
+
+        Some(Repair {
+            moves: update.len() - stay,
+            swaps: swaps[full],
+        })
+    }
+
+    /// With a total order the pages that stay put are a longest run already
+    /// in rule order, and every inverted pair costs exactly one swap
+    fn repair_cost_total(self: &Self, update: &[u8]) -> Repair {
+        let mut longest = vec![1; update.len()];
+        let mut swaps = 0;
+        for j in 0..update.len() {
+            for i in 0..j {
+                if self.requires(update[i], update[j]) {
+                    longest[j] = longest[j].max(longest[i] + 1);
+                } else {
+                    swaps += 1;
+                }
+            }
+        }
+
+        Repair {
+            moves: update.len() - longest.into_iter().max().unwrap_or(0),
+            swaps,
+        }
+    }
+
+    pub fn reorder(self: &Self, update: &Vec<u8>, mode: Mode) -> Result<Vec<u8>, Cycle> {
+        self.top.sort(update, mode)
+    }
+}
+
+/// A rule `prior|posterior` broken by `posterior` turning up first
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Violation {
+    pub prior: u8,
+    pub posterior: u8,
+    pub prior_at: usize,
+    pub posterior_at: usize,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rule {}|{} is broken: {} is at position {}, but {} is at {}",
+            self.prior,
+            self.posterior,
+            self.posterior,
+            self.posterior_at,
+            self.prior,
+            self.prior_at
+        )
+    }
+}
+
+/// How far an update is from correct
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Repair {
+    /// pages taken out and put back anywhere
+    pub moves: usize,
+    /// swaps of neighbouring pages
+    pub swaps: usize,
 }
 
+/// `count_orderings` keeps one `u64` per subset of the update's pages
+pub const MAX_COUNTED_LEN: usize = 20;
+
+/// `orderings` and `unconstrained_pairs` keep a `u64` bitmask of pages
+pub const MAX_MASKED_LEN: usize = 64;
+
+/// A depth first walk over the orderings of an update that satisfy the rules
+pub struct Orderings {
+    pages: Vec<u8>,
+    preds: Vec<u64>,
+    /// indices into `pages`, in the order placed so far
+    prefix: Vec<usize>,
+    placed: u64,
+    /// at each depth, the first index still to try there
+    cursors: Vec<usize>,
+    done: bool,
+}
+
+impl Iterator for Orderings {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let depth = self.prefix.len();
+
+            if depth == self.pages.len() {
+                let ordering = self.prefix.iter().map(|i| self.pages[*i]).collect();
+                self.backtrack();
+                return Some(ordering);
+            }
+
+            let candidate = (self.cursors[depth]..self.pages.len())
+                .find(|i| self.placed & 1 << i == 0 && self.preds[*i] & !self.placed == 0);
+
+            match candidate {
+                Some(i) => {
+                    self.cursors[depth] = i + 1;
+                    self.cursors[depth + 1] = 0;
+                    self.prefix.push(i);
+                    self.placed |= 1 << i;
+                }
+                None => self.backtrack(),
+            }
+        }
+
+        None
+    }
+}
+
+impl Orderings {
+    fn backtrack(&mut self) {
+        match self.prefix.pop() {
+            Some(i) => self.placed &= !(1 << i),
+            None => self.done = true,
+        }
+    }
+}
+
+/// What `TopologicalSort::sort` does when the rules among a list's pages
+/// form a cycle
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// fail, reporting the cycle
+    Strict,
+    /// put the pages it could order first, and the rest after in their given order
+    BestEffort,
+}
+
+/// Pages whose rules require each to come before the next, and the last
+/// before the first
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cycle {
+    pub pages: Vec<u8>,
+}
+
+impl std::fmt::Display for Cycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the rules form a cycle: ")?;
+        for page in &self.pages {
+            write!(f, "{page} -> ")?;
+        }
+        write!(f, "{}", self.pages[0])
+    }
+}
+
+impl Error for Cycle {}
+
 pub struct TopologicalSort {
     forward: HashMap<u8, HashSet<u8>>,
     backward: HashMap<u8, HashSet<u8>>,
 }
 
 impl TopologicalSort {
-    pub fn sort(self: &Self, list: &Vec<u8>) -> Vec<u8> {
-        let mut out: Vec<u8> = vec![];
-        let list_as_set = list.iter().map(|e| *e).collect::<HashSet<_>>();
-        let mut backward = list
-            .iter()
-            .filter_map(|key| {
-                self.backward.get(key).map(|value| {
-                    (
-                        *key,
-                        value
-                            .intersection(&list_as_set)
-                            .map(|e| *e)
-                            .to_owned()
-                            .collect::<HashSet<_>>(),
-                    )
-                })
-            })
-            .collect::<HashMap<_, _>>();
+    /// Kahn's algorithm over the rules between `list`'s pages. Ties are
+    /// broken by position in `list`, so the result is deterministic.
+    pub fn sort(self: &Self, list: &Vec<u8>, mode: Mode) -> Result<Vec<u8>, Cycle> {
+        let list_as_set = list.iter().copied().collect::<HashSet<_>>();
+        let parents = |page: &u8| {
+            self.backward
+                .get(page)
+                .into_iter()
+                .flatten()
+                .filter(|parent| list_as_set.contains(parent))
+        };
 
-        let mut forward = list
+        let mut in_degree = list
             .iter()
-            .filter_map(|key| {
-                self.forward.get(key).map(|value| {
-                    (
-                        *key,
-                        value
-                            .intersection(&list_as_set)
-                            .map(|e| *e)
-                            .to_owned()
-                            .collect::<HashSet<_>>(),
-                    )
-                })
-            })
+            .map(|page| (*page, parents(page).count()))
             .collect::<HashMap<_, _>>();
-        let mut frontier: VecDeque<u8> = {
-            let frontier: VecDeque<u8> = backward
-                .iter()
-                .filter_map(|(key, value)| if value.is_empty() { Some(*key) } else { None })
-                .collect::<VecDeque<u8>>();
-
-            frontier
-        };
+        let mut frontier = list
+            .iter()
+            .filter(|page| in_degree[page] == 0)
+            .copied()
+            .collect::<VecDeque<u8>>();
 
-        while !frontier.is_empty() {
-            let u = frontier.pop_front().unwrap();
+        let mut out: Vec<u8> = vec![];
+        while let Some(u) = frontier.pop_front() {
             out.push(u);
-            for (key_v, v_parents) in backward.iter_mut().filter(|(_, h)| h.contains(&u)) {
-                v_parents.remove(&u);
-                if let Some(children) = forward.get_mut(&u) {
-                    children.remove(key_v);
-                }
-                if v_parents.is_empty() {
-                    frontier.push_back(*key_v);
+            let Some(children) = self.forward.get(&u) else {
+                continue;
+            };
+            for v in list.iter().filter(|v| children.contains(v)) {
+                let degree = in_degree.get_mut(v).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    frontier.push_back(*v);
                 }
             }
         }
+
+        if out.len() == list.len() {
+            return Ok(out);
+        }
+
         let rest = list
             .iter()
-            .filter(|c| !out.contains(c))
-            .map(|c| *c)
+            .filter(|c| in_degree[c] > 0)
+            .copied()
             .collect::<Vec<_>>();
-        [out, rest].concat()
+
+        match mode {
+            Mode::BestEffort => Ok([out, rest].concat()),
+            Mode::Strict => Err(self.find_cycle(&rest)),
+        }
+    }
+
+    /// Every page Kahn's algorithm left behind still has a parent that was
+    /// left behind too, so walking parents from any of them must revisit a page.
+    fn find_cycle(self: &Self, rest: &[u8]) -> Cycle {
+        let rest_as_set = rest.iter().copied().collect::<HashSet<_>>();
+        let mut walk = vec![rest[0]];
+
+        loop {
+            let u = *walk.last().unwrap();
+            let parent = rest
+                .iter()
+                .find(|p| self.backward[&u].contains(p) && rest_as_set.contains(p))
+                .copied()
+                .unwrap();
+
+            if let Some(start) = walk.iter().position(|p| *p == parent) {
+                let mut pages = walk.split_off(start);
+                // parents were followed backwards, so flip to rule order
+                pages[1..].reverse();
+                return Cycle { pages };
+            }
+            walk.push(parent);
+        }
     }
 
     fn from_constraints(constraints: Vec<(u8, u8)>) -> TopologicalSort {
@@ -185,10 +627,16 @@ fn handle_puzzle2(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
             panic!("unexpected even length input");
         }
 
-        if !constraints.is_correct(update) {
-            let update = constraints.reorder(update);
-            sum += update[update.len() / 2] as u32;
+        if constraints.is_correct(update) {
+            continue;
+        }
+
+        if constraints.is_total(update) {
+            constraints.sort_by_rules(update);
+        } else {
+            *update = constraints.reorder(update, Mode::Strict)?;
         }
+        sum += update[update.len() / 2] as u32;
     }
 
     Ok(sum)
@@ -265,3 +713,179 @@ fn test_puzzle2() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_sort_cycle() -> Result<(), Box<dyn std::error::Error>> {
+    let constraints = Constraints::new(vec![(1, 2), (2, 3), (3, 1), (4, 2), (5, 6)]);
+
+    assert_eq!(
+        constraints.reorder(&vec![6, 3, 4, 1, 5, 2], Mode::Strict),
+        Err(Cycle {
+            pages: vec![3, 1, 2]
+        })
+    );
+    assert_eq!(
+        constraints
+            .reorder(&vec![6, 3, 4, 1, 5, 2], Mode::Strict)
+            .unwrap_err()
+            .to_string(),
+        "the rules form a cycle: 3 -> 1 -> 2 -> 3"
+    );
+    assert_eq!(
+        constraints.reorder(&vec![6, 3, 4, 1, 5, 2], Mode::BestEffort),
+        Ok(vec![4, 5, 6, 3, 1, 2])
+    );
+
+    // dropping one page from the cycle leaves it orderable either way
+    assert_eq!(
+        constraints.reorder(&vec![3, 2, 4], Mode::Strict),
+        Ok(vec![4, 2, 3])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_sort_by_rules() -> Result<(), Box<dyn std::error::Error>> {
+    let constraints = Constraints::new(vec![(1, 2), (2, 3), (1, 3), (200, 1), (200, 2), (200, 3)]);
+
+    let mut update = vec![3, 2, 200, 1];
+    assert!(!constraints.is_correct(&update));
+    assert!(constraints.is_total(&update));
+
+    let reordered = constraints.reorder(&update, Mode::Strict)?;
+    constraints.sort_by_rules(&mut update);
+    assert_eq!(update, vec![200, 1, 2, 3]);
+    assert_eq!(update, reordered);
+    assert!(constraints.is_correct(&update));
+
+    // 4 has no rules at all, so the comparator alone can't place it
+    assert!(constraints.is_correct(&vec![4, 3]));
+    assert!(!constraints.is_total(&[1, 4, 3]));
+
+    // every pair has a rule, but they go round in a circle, so there's no
+    // order to sort into and part 2 has to report the cycle instead
+    let cyclic = Constraints::new(vec![(1, 2), (2, 3), (3, 1)]);
+    assert!(!cyclic.is_total(&[1, 2, 3]));
+    assert!(cyclic.is_total(&[1, 2]));
+    assert_eq!(
+        handle_puzzle2("1|2\n2|3\n3|1\n\n3,2,1")
+            .unwrap_err()
+            .to_string(),
+        "the rules form a cycle: 3 -> 1 -> 2 -> 3"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_orderings() -> Result<(), Box<dyn std::error::Error>> {
+    let constraints = Constraints::new(vec![(1, 2), (1, 3), (3, 4), (5, 6), (6, 5)]);
+
+    assert_eq!(constraints.count_orderings(&[3, 2, 1]), Some(2));
+    assert_eq!(
+        constraints
+            .orderings(&[3, 2, 1])
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![vec![1, 3, 2], vec![1, 2, 3]]
+    );
+    assert_eq!(
+        constraints.unconstrained_pairs(&[3, 2, 1]),
+        Some(vec![(3, 2)])
+    );
+
+    // 1 comes before 4 through 3, so only 2 floats
+    assert_eq!(
+        constraints.unconstrained_pairs(&[4, 2, 1, 3]),
+        Some(vec![(4, 2), (2, 3)])
+    );
+
+    // a cycle has no valid orderings at all
+    assert_eq!(constraints.count_orderings(&[5, 6]), Some(0));
+    assert_eq!(constraints.orderings(&[5, 6]).unwrap().next(), None);
+
+    // no rules: every permutation, and the counter agrees with the enumerator
+    let free = [10, 11, 12, 13, 14, 15];
+    assert_eq!(constraints.count_orderings(&free), Some(720));
+    assert_eq!(constraints.orderings(&free).unwrap().count(), 720);
+    let mixed = [4, 2, 10, 1, 3, 11];
+    assert_eq!(
+        constraints.count_orderings(&mixed),
+        Some(constraints.orderings(&mixed).unwrap().count() as u64)
+    );
+
+    assert_eq!(constraints.count_orderings(&[0; MAX_COUNTED_LEN + 1]), None);
+
+    // too long for a bitmask, but still a valid update, so no panic
+    let long = (0..=MAX_MASKED_LEN as u8).collect::<Vec<_>>();
+    assert!(constraints.orderings(&long).is_none());
+    assert_eq!(constraints.unconstrained_pairs(&long), None);
+    assert_eq!(constraints.repair_cost(&long), None);
+
+    // a long update with a rule for every pair is still repaired directly
+    let chain = (0..=MAX_MASKED_LEN as u8)
+        .flat_map(|a| (a + 1..=MAX_MASKED_LEN as u8).map(move |b| (a, b)))
+        .collect::<Vec<_>>();
+    let mut reversed = long.clone();
+    reversed.reverse();
+    assert_eq!(
+        Constraints::new(chain).repair_cost(&reversed),
+        Some(Repair {
+            moves: MAX_MASKED_LEN,
+            swaps: MAX_MASKED_LEN * (MAX_MASKED_LEN + 1) / 2
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_violations() -> Result<(), Box<dyn std::error::Error>> {
+    let rules = "47|53\n97|13\n97|61\n97|47\n75|29\n61|13\n75|53\n29|13\n97|29\n53|29\n61|53\n97|53\n61|29\n47|13\n75|47\n97|75\n47|61\n75|61\n47|29\n75|13\n53|13";
+    let (_, constraints) = load_puzzle(&format!("{rules}\n\n1"))?;
+
+    assert_eq!(constraints.violations(&[75, 47, 61, 53, 29]), vec![]);
+    assert_eq!(
+        constraints.repair_cost(&[75, 47, 61, 53, 29]),
+        Some(Repair { moves: 0, swaps: 0 })
+    );
+
+    let violations = constraints.violations(&[75, 97, 47, 61, 53]);
+    assert_eq!(
+        violations,
+        vec![Violation {
+            prior: 97,
+            posterior: 75,
+            prior_at: 1,
+            posterior_at: 0
+        }]
+    );
+    assert_eq!(
+        violations[0].to_string(),
+        "rule 97|75 is broken: 75 is at position 0, but 97 is at 1"
+    );
+    assert_eq!(
+        constraints.repair_cost(&[75, 97, 47, 61, 53]),
+        Some(Repair { moves: 1, swaps: 1 })
+    );
+
+    // 13 has to pass three pages, and 29 has to pass 47
+    let update = [97, 13, 75, 29, 47];
+    assert_eq!(constraints.violations(&update).len(), 4);
+    assert_eq!(
+        constraints.repair_cost(&update),
+        Some(Repair { moves: 2, swaps: 4 })
+    );
+
+    // 1 and 2 are unrelated, but getting 3 ahead of 1 takes two swaps either
+    // way: 3 passes 2 too, or 1 passes 2 on its way to the back
+    let partial = Constraints::new(vec![(3, 1), (5, 6), (6, 5)]);
+    assert_eq!(
+        partial.repair_cost(&[1, 2, 3]),
+        Some(Repair { moves: 1, swaps: 2 })
+    );
+    assert_eq!(partial.repair_cost(&[5, 6]), None);
+
+    Ok(())
+}
@@ -1,6 +1,11 @@
-use itertools::iproduct;
+mod jump_table;
+mod patrol;
+mod render;
+mod simulator;
 use rayon::prelude::*;
-use std::{collections::HashSet, sync::Arc};
+
+use jump_table::JumpTable;
+use simulator::{Lab, Simulator, Tracking};
 
 pub fn puzzle1() -> Result<u32, Box<dyn std::error::Error>> {
     handle_puzzle1(std::fs::read_to_string("./inputs/dec06.txt")?.as_str())
@@ -10,6 +15,36 @@ pub fn puzzle2() -> Result<u32, Box<dyn std::error::Error>> {
     handle_puzzle2(std::fs::read_to_string("./inputs/dec06.txt")?.as_str())
 }
 
+/// Runs every guard on the map at once, turning `left`, `right` or `reverse`,
+/// and prints how each fared, how they fared together, and any collisions
+pub fn patrol(turn: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let turn = match turn {
+        "left" => simulator::Turn::Left,
+        "right" => simulator::Turn::Right,
+        "reverse" => simulator::Turn::Reverse,
+        _ => return Err(format!("unknown turn `{turn}`").into()),
+    };
+    let mut lab = Lab::load_from_string(&std::fs::read_to_string("./inputs/dec06.txt")?)?;
+    for guard in &mut lab.guards {
+        guard.turn = turn;
+    }
+
+    let max_ticks = 1 << 20;
+    let report = patrol::Patrol::new(&lab).run(max_ticks);
+    for (i, outcome) in report.outcomes.iter().enumerate() {
+        println!("guard {i}: {outcome:?}");
+    }
+    println!("together: {:?}", report.joint);
+    for collision in &report.collisions {
+        println!("{collision:?}");
+    }
+    if report.truncated {
+        println!("stopped looking for collisions after {max_ticks} ticks");
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 enum Direction {
     North,
@@ -18,296 +53,308 @@ enum Direction {
     West,
 }
 
+impl Direction {
+    pub fn turn_right(self) -> Self {
+        match self {
+            Direction::North => Direction::East,
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+        }
+    }
+
+    /// a dense index, for tables with an entry per direction
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
 #[derive(Clone, Copy)]
 enum Square {
     Guard(Direction),
     Empty,
     Terrain,
+    /// only lets guards through heading this way; from any other side it's terrain
+    Door(Direction),
+    /// steps onto it come out on the other teleporter with the same label
+    Teleporter(u8),
 }
 
 impl Square {
-    pub fn parse(c: &u8) -> Self {
-        match c {
+    pub fn parse(c: &u8) -> Option<Self> {
+        let square = match c {
             b'.' => Square::Empty,
             b'^' => Square::Guard(Direction::North),
             b'v' => Square::Guard(Direction::South),
             b'<' => Square::Guard(Direction::West),
             b'>' => Square::Guard(Direction::East),
             b'#' => Square::Terrain,
-            _ => unreachable!(),
-        }
+            b'N' => Square::Door(Direction::North),
+            b'S' => Square::Door(Direction::South),
+            b'E' => Square::Door(Direction::East),
+            b'W' => Square::Door(Direction::West),
+            b'0'..=b'9' => Square::Teleporter(*c),
+            _ => return None,
+        };
+
+        Some(square)
     }
 }
 
-#[derive(Clone)]
-struct Engine {
-    state: Vec<Vec<Square>>,
-    guard_location: Option<(usize, usize)>,
-    guard_visited: HashSet<(usize, usize)>,
+/// Plays the guard's patrol in the terminal, a frame per tick
+pub fn animate(frames_per_second: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let lab = Lab::load_from_string(&std::fs::read_to_string("./inputs/dec06.txt")?)?;
+    let mut animation = render::Animation::new(&lab, None, std::io::stdout(), frames_per_second);
+    Simulator::new(&lab, lab.guard(), Tracking::Off).run(&mut animation);
+
+    match animation.take_error() {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
 }
 
-impl Engine {
-    pub fn load_from_string(input: &str) -> Self {
-        let mut state = vec![];
-        let mut guard_location = None;
-        for (i, line) in input.lines().enumerate() {
-            let mut next = vec![];
-            for (j, cell) in line.as_bytes().iter().enumerate() {
-                let square = Square::parse(cell);
-                if let Square::Guard(_) = square {
-                    guard_location = Some((i, j));
-                }
-                next.push(square);
-            }
+fn handle_puzzle1(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let lab = Lab::load_from_string(input)?;
+    let mut simulator = Simulator::new(&lab, lab.guard(), Tracking::Cells);
+    simulator.run(&mut ());
 
-            state.push(next);
-        }
+    Ok(simulator.visited_cells().len() as u32)
+}
 
-        Self {
-            state,
-            guard_location,
-            guard_visited: HashSet::new(),
-        }
-    }
+fn handle_puzzle2(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let lab = Lab::load_from_string(input)?;
 
-    pub fn count_distinct_positions(self: &Self) -> usize {
-        self.guard_visited.len()
-    }
+    Ok(looping_obstructions(&lab)? as u32)
 }
 
-impl Iterator for Engine {
-    type Item = ();
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let height = self.state.len();
-        let width = self.state[0].len();
-        let Some(guard_location) = self.guard_location else {
-            panic!("guard_location unexpectedly empty")
-        };
-        self.guard_visited.insert(guard_location);
-        let (i, j) = guard_location;
-        let Square::Guard(mut direction) = self.state[i][j] else {
-            panic!("guard unexpectedly missing")
-        };
-        let (mut new_i, mut new_j) = (i as i64, j as i64);
-        match direction {
-            Direction::North => {
-                if 0 < i {
-                    match self.state[i - 1][j] {
-                        Square::Terrain => direction = Direction::East,
-                        Square::Empty => new_i -= 1,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    new_i -= 1;
-                }
-            }
-            Direction::South => {
-                if i < height - 1 {
-                    match self.state[i + 1][j] {
-                        Square::Terrain => direction = Direction::West,
-                        Square::Empty => new_i += 1,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    new_i += 1;
-                }
-            }
-            Direction::East => {
-                if j < width - 1 {
-                    match self.state[i][j + 1] {
-                        Square::Terrain => direction = Direction::South,
-                        Square::Empty => new_j += 1,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    new_j += 1;
-                }
-            }
-            Direction::West => {
-                if 0 < j {
-                    match self.state[i][j - 1] {
-                        Square::Terrain => direction = Direction::North,
-                        Square::Empty => new_j -= 1,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    new_j -= 1;
-                }
-            }
-        }
-        self.state[i][j] = Square::Empty;
-        if new_i < 0 || new_i as usize >= height || new_j < 0 || new_j as usize >= width {
-            None // guard has left the map, so terminate the iterator
-        } else {
-            self.guard_location = Some((new_i as usize, new_j as usize));
-            self.state[new_i as usize][new_j as usize] = Square::Guard(direction);
-            Some(())
-        }
+/// How many empty cells an obstacle could go on to trap the lab's guard in a
+/// loop. The jump table answers for the puzzle's own labs; anything it can't
+/// model (doors, teleporters, ragged rows, other turns) is stepped through
+/// with the simulator instead. There's no one answer for several guards.
+fn looping_obstructions(lab: &Lab) -> Result<usize, String> {
+    if lab.guards.len() != 1 {
+        return Err(format!(
+            "obstructions trap a single guard, but there are {}",
+            lab.guards.len()
+        ));
     }
+    let guard = lab.guard();
+
+    // an obstacle off the guard's original path can't change it
+    let mut simulator = Simulator::new(lab, guard, Tracking::Cells);
+    simulator.run(&mut ());
+    let path = simulator
+        .visited_cells()
+        .into_iter()
+        .filter(|(i, j)| (*i, *j) != guard.cell && matches!(lab.state[*i][*j], Square::Empty))
+        .collect::<Vec<_>>();
+
+    let count = if JumpTable::models(lab) {
+        let table = JumpTable::new(&lab.state);
+        path.par_iter()
+            .filter(|cell| {
+                table
+                    .with_obstacle(**cell)
+                    .loops(guard.cell, guard.direction)
+            })
+            .count()
+    } else {
+        path.par_iter()
+            .filter(|(i, j)| {
+                let mut blocked = lab.clone();
+                blocked.state[*i][*j] = Square::Terrain;
+                let outcome = Simulator::new(&blocked, guard, Tracking::Off).run(&mut ());
+                matches!(outcome, simulator::Outcome::Looped { .. })
+            })
+            .count()
+    };
+
+    Ok(count)
 }
 
-fn handle_puzzle1(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
-    let mut engine = Engine::load_from_string(input);
+#[test]
+fn test_puzzle1() -> Result<(), Box<dyn std::error::Error>> {
+    let input = r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#;
 
-    // step through the engine
-    for () in &mut engine {}
+    assert_eq!(handle_puzzle1(input)?, 41);
 
-    Ok(engine.count_distinct_positions() as u32)
+    Ok(())
 }
 
-#[derive(Clone)]
-struct Engine2 {
-    state: Vec<Vec<Square>>,
-    guard_location: Option<(usize, usize)>,
-    guard_path: HashSet<(usize, usize, Direction)>,
-    has_loop: Option<bool>,
-}
+#[test]
+fn test_puzzle2() -> Result<(), Box<dyn std::error::Error>> {
+    let input = r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#;
 
-impl Engine2 {
-    pub fn load_from_string(input: &str) -> Self {
-        let mut state = vec![];
-        let mut guard_location = None;
-        for (i, line) in input.lines().enumerate() {
-            let mut next = vec![];
-            for (j, cell) in line.as_bytes().iter().enumerate() {
-                let square = Square::parse(cell);
-                if let Square::Guard(_) = square {
-                    guard_location = Some((i, j));
-                }
-                next.push(square);
-            }
+    assert_eq!(handle_puzzle2(input)?, 6);
 
-            state.push(next);
-        }
+    Ok(())
+}
 
-        Self {
-            state,
-            guard_location,
-            guard_path: HashSet::new(),
-            has_loop: None,
-        }
-    }
+#[test]
+fn test_jump_table_matches_simulator() -> Result<(), Box<dyn std::error::Error>> {
+    use simulator::Outcome;
 
-    fn loop_detected(&self) -> Option<bool> {
-        self.has_loop
-    }
-}
+    let input = r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#;
 
-impl Iterator for Engine2 {
-    type Item = ();
+    let lab = Lab::load_from_string(input)?;
+    let table = JumpTable::new(&lab.state);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let height = self.state.len();
-        let width = self.state[0].len();
-        let Some(guard_location) = self.guard_location else {
-            panic!("guard_location unexpectedly empty")
-        };
-        let (i, j) = guard_location;
-        let Square::Guard(mut direction) = self.state[i][j] else {
-            panic!("guard unexpectedly missing")
-        };
-        self.guard_path.insert((i, j, direction));
-        let (mut new_i, mut new_j) = (i as i64, j as i64);
-        match direction {
-            Direction::North => {
-                if 0 < i {
-                    match self.state[i - 1][j] {
-                        Square::Terrain => direction = Direction::East,
-                        Square::Empty => new_i -= 1,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    new_i -= 1;
-                }
+    // every empty cell, not just those on the path, against stepping cell by
+    // cell, where Brent's algorithm agrees with remembering every state
+    for (y, row) in lab.state.iter().enumerate() {
+        for (x, square) in row.iter().enumerate() {
+            if !matches!(square, Square::Empty) || (y, x) == lab.guard().cell {
+                continue;
             }
-            Direction::South => {
-                if i < height - 1 {
-                    match self.state[i + 1][j] {
-                        Square::Terrain => direction = Direction::West,
-                        Square::Empty => new_i += 1,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    new_i += 1;
-                }
-            }
-            Direction::East => {
-                if j < width - 1 {
-                    match self.state[i][j + 1] {
-                        Square::Terrain => direction = Direction::South,
-                        Square::Empty => new_j += 1,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    new_j += 1;
-                }
-            }
-            Direction::West => {
-                if 0 < j {
-                    match self.state[i][j - 1] {
-                        Square::Terrain => direction = Direction::North,
-                        Square::Empty => new_j -= 1,
-                        _ => unreachable!(),
-                    }
-                } else {
-                    new_j -= 1;
-                }
-            }
-        }
-        self.state[i][j] = Square::Empty;
-        if new_i < 0 || new_i as usize >= height || new_j < 0 || new_j as usize >= width {
-            self.has_loop = Some(false);
-            None // guard has left the map, so terminate the iterator
-        } else if self
-            .guard_path
-            .contains(&(new_i as usize, new_j as usize, direction))
-        {
-            self.has_loop = Some(true);
-            None
-        } else {
-            self.guard_location = Some((new_i as usize, new_j as usize));
-            self.state[new_i as usize][new_j as usize] = Square::Guard(direction);
-            Some(())
+
+            let mut blocked = lab.clone();
+            blocked.state[y][x] = Square::Terrain;
+            let outcome = Simulator::new(&blocked, lab.guard(), Tracking::States).run(&mut ());
+            assert_eq!(
+                Simulator::new(&blocked, lab.guard(), Tracking::Off).run(&mut ()),
+                outcome
+            );
+
+            assert_eq!(
+                table
+                    .with_obstacle((y, x))
+                    .loops(lab.guard().cell, lab.guard().direction),
+                matches!(outcome, Outcome::Looped { .. }),
+                "obstacle at {:?}",
+                (y, x)
+            );
         }
     }
+
+    Ok(())
 }
 
-fn handle_puzzle2(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
-    let base_engine = Engine2::load_from_string(input);
-    let height = base_engine.state.len();
-    let width = base_engine.state[0].len();
-    let search_space = iproduct!(0..height, 0..width);
-
-    let sum = search_space
-        .par_bridge()
-        .map(|(y, x)| {
-            // Technically, time could factor into this, as loops
-            // with dynamically added terrain may differ from loops with obstacle
-            // added only at the start.
-
-            // Todo: possibly need to add check that obstacle is not directly in
-            //       front of guard.
-            if matches!(base_engine.state[y][x], Square::Empty) {
-                let mut engine = base_engine.clone();
-                engine.state[y][x] = Square::Terrain;
-
-                for () in &mut engine {}
-
-                if let Some(true) = engine.loop_detected() {
-                    return 1;
+#[test]
+fn test_obstructions_beyond_the_jump_table() -> Result<(), Box<dyn std::error::Error>> {
+    use simulator::{Outcome, Turn};
+
+    let input = r#"....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#..."#;
+
+    // every empty cell, stepped through with every state remembered
+    let brute_force = |lab: &Lab| {
+        let mut count = 0;
+        for (y, row) in lab.state.iter().enumerate() {
+            for (x, square) in row.iter().enumerate() {
+                if !matches!(square, Square::Empty) || (y, x) == lab.guard().cell {
+                    continue;
                 }
-            }
 
-            0
-        })
-        .sum();
+                let mut blocked = lab.clone();
+                blocked.state[y][x] = Square::Terrain;
+                let outcome = Simulator::new(&blocked, lab.guard(), Tracking::States).run(&mut ());
+                count += matches!(outcome, Outcome::Looped { .. }) as usize;
+            }
+        }
+        count
+    };
+
+    let lab = Lab::load_from_string(input)?;
+    assert!(JumpTable::models(&lab));
+    assert_eq!(looping_obstructions(&lab)?, brute_force(&lab));
+
+    let mut left = lab.clone();
+    left.guards[0].turn = Turn::Left;
+    assert!(!JumpTable::models(&left));
+    assert_eq!(looping_obstructions(&left)?, brute_force(&left));
+
+    // a door the guard can walk through northwards, and a pair of teleporters
+    let gadgets =
+        input
+            .replacen("....#.....", "....N.....", 1)
+            .replacen("#.........", "#1.......1", 1);
+    let gadgets = Lab::load_from_string(&gadgets)?;
+    assert!(!JumpTable::models(&gadgets));
+    assert_eq!(looping_obstructions(&gadgets)?, brute_force(&gadgets));
+
+    // ragged rows, where walking past the end of a short row leaves the lab
+    let ragged = input
+        .lines()
+        .enumerate()
+        .map(|(i, line)| &line[..line.len() - i % 3])
+        .collect::<Vec<_>>()
+        .join("\n");
+    let ragged = Lab::load_from_string(&ragged)?;
+    assert!(!JumpTable::models(&ragged));
+    assert_eq!(looping_obstructions(&ragged)?, brute_force(&ragged));
+    assert_eq!(handle_puzzle1("..v\n..")?, 1);
+    assert_eq!(handle_puzzle2("...v\n..\n....")?, 0);
+
+    assert_eq!(
+        handle_puzzle2(">..\n..<").unwrap_err().to_string(),
+        "obstructions trap a single guard, but there are 2"
+    );
 
-    Ok(sum)
+    Ok(())
 }
 
 #[test]
-fn test_puzzle1() -> Result<(), Box<dyn std::error::Error>> {
+fn test_observer() -> Result<(), Box<dyn std::error::Error>> {
+    use simulator::{Guard, Observer, Outcome};
+
+    #[derive(Default)]
+    struct Counts {
+        steps: usize,
+        turns: usize,
+        exits: usize,
+        loops: usize,
+    }
+
+    impl Observer for Counts {
+        fn on_step(&mut self, _from: Guard, _to: Guard) {
+            self.steps += 1;
+        }
+        fn on_turn(&mut self, _guard: Guard, _to: Direction) {
+            self.turns += 1;
+        }
+        fn on_exit(&mut self, _guard: Guard) {
+            self.exits += 1;
+        }
+        fn on_loop(&mut self, _guard: Guard) {
+            self.loops += 1;
+        }
+    }
+
     let input = r#"....#.....
 .........#
 ..........
@@ -319,13 +366,136 @@ fn test_puzzle1() -> Result<(), Box<dyn std::error::Error>> {
 #.........
 ......#..."#;
 
-    assert_eq!(handle_puzzle1(input)?, 41);
+    let mut lab = Lab::load_from_string(input)?;
+    let mut counts = Counts::default();
+    let outcome = Simulator::new(&lab, lab.guard(), Tracking::Off).run(&mut counts);
+
+    assert_eq!(outcome, Outcome::Exited { ticks: 54 });
+    assert_eq!((counts.steps, counts.turns, counts.exits), (44, 10, 1));
+
+    // the puzzle's first looping obstacle, next to the guard's start
+    lab.state[6][3] = Square::Terrain;
+    let mut counts = Counts::default();
+    let mut simulator = Simulator::new(&lab, lab.guard(), Tracking::States);
+    let outcome = simulator.run(&mut counts);
+
+    assert_eq!(
+        outcome,
+        Outcome::Looped {
+            cycle_start: 0,
+            cycle_len: counts.steps + counts.turns
+        }
+    );
+    assert_eq!(counts.loops, 1);
+    assert!(simulator.visited_cells().contains(&(6, 4)));
 
     Ok(())
 }
 
 #[test]
-fn test_puzzle2() -> Result<(), Box<dyn std::error::Error>> {
+fn test_patrol() -> Result<(), Box<dyn std::error::Error>> {
+    use patrol::{Collision, Patrol};
+    use simulator::{Outcome, Turn};
+
+    // the top guard goes round its box every 8 ticks and the bottom one
+    // bounces between the walls every 6, so together they repeat every 24
+    let input = r#".##.......
+#^.#......
+#..#......
+.##.......
+#>..#....."#;
+
+    let mut lab = Lab::load_from_string(input)?;
+    lab.guards[1].turn = Turn::Reverse;
+    let report = Patrol::new(&lab).run(1000);
+
+    assert_eq!(
+        report.outcomes,
+        vec![
+            Outcome::Looped {
+                cycle_start: 0,
+                cycle_len: 8
+            },
+            Outcome::Looped {
+                cycle_start: 0,
+                cycle_len: 6
+            }
+        ]
+    );
+    assert_eq!(
+        report.joint,
+        Outcome::Looped {
+            cycle_start: 0,
+            cycle_len: 24
+        }
+    );
+    assert!(report.collisions.is_empty());
+    assert!(!report.truncated);
+
+    // two guards walking at each other cross over, or meet head on
+    let crossing = Lab::load_from_string(">.<\n....\n>..<")?;
+    let report = Patrol::new(&crossing).run(1000);
+    assert_eq!(
+        report.collisions,
+        vec![
+            Collision::Meeting {
+                tick: 1,
+                cell: (0, 1),
+                guards: (0, 1)
+            },
+            Collision::Crossing {
+                tick: 2,
+                cells: ((2, 1), (2, 2)),
+                guards: (2, 3)
+            }
+        ]
+    );
+    assert_eq!(report.joint, Outcome::Exited { ticks: 3 });
+
+    // a door only opens one way, and a teleporter carries the guard through
+    let path = |input: &str| -> Result<Vec<_>, String> {
+        let lab = Lab::load_from_string(input)?;
+        let mut path = vec![];
+        let mut guard = Some(lab.guard());
+        while let Some(g) = guard {
+            path.push((g.cell, g.direction));
+            guard = lab.tick(g);
+        }
+        Ok(path)
+    };
+    assert_eq!(
+        path("1W<\n..1")?,
+        vec![
+            ((0, 2), Direction::West),
+            ((0, 1), Direction::West),
+            ((1, 2), Direction::West),
+            ((1, 1), Direction::West),
+            ((1, 0), Direction::West),
+        ]
+    );
+    assert_eq!(
+        path("E<\n..")?,
+        vec![((0, 1), Direction::West), ((0, 1), Direction::North)]
+    );
+
+    assert_eq!(
+        Lab::load_from_string("..?\n.^.").err(),
+        Some("unexpected `?` at line 1, column 3".to_string())
+    );
+    assert_eq!(
+        Lab::load_from_string("1.1\n.^1").err(),
+        Some("teleporter `1` appears 3 times, not twice".to_string())
+    );
+    assert!(Lab::load_from_string("...").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_render() -> Result<(), Box<dyn std::error::Error>> {
+    use render::{render, Animation, Trail};
+    use simulator::Outcome;
+
     let input = r#"....#.....
 .........#
 ..........
@@ -337,7 +507,46 @@ fn test_puzzle2() -> Result<(), Box<dyn std::error::Error>> {
 #.........
 ......#..."#;
 
-    assert_eq!(handle_puzzle2(input)?, 6);
+    // the puzzle's picture of its first looping obstacle
+    let mut lab = Lab::load_from_string(input)?;
+    lab.state[6][3] = Square::Terrain;
+    let mut trail = Trail::default();
+    Simulator::new(&lab, lab.guard(), Tracking::Off).run(&mut trail);
+
+    assert_eq!(
+        render(&lab, &trail, Some(lab.guard()), Some((6, 3))),
+        r#"....#.....
+....+---+#
+....|...|.
+..#.|...|.
+....|..#|.
+....|...|.
+.#.O^---+.
+........#.
+#.........
+......#...
+"#
+    );
+
+    // a frame per tick, the last with the guard about to walk off
+    let lab = Lab::load_from_string(input)?;
+    let mut frames = vec![];
+    let mut animation = Animation::new(&lab, None, &mut frames, 0);
+    let outcome = Simulator::new(&lab, lab.guard(), Tracking::Off).run(&mut animation);
+    assert!(animation.take_error().is_none());
+
+    let frames = String::from_utf8(frames)?;
+    assert_eq!(
+        Outcome::Exited {
+            ticks: frames.matches("\x1b[2J").count()
+        },
+        outcome
+    );
+    assert!(frames.ends_with(
+        r#"#+----+|..
+......#v..
+"#
+    ));
 
     Ok(())
 }
@@ -0,0 +1,140 @@
+use super::simulator::{Lab, Turn};
+use super::{Direction, Square};
+
+type Cell = (usize, usize);
+
+/// For every cell and heading, where a guard walking straight ahead from that
+/// cell stops: the last cell before an obstacle, or `None` if it walks off
+/// the map. A guard can then teleport from turn to turn. Only terrain is
+/// understood, and guards turn right, as in the puzzle.
+pub(crate) struct JumpTable {
+    width: usize,
+    stops: Vec<[Option<Cell>; 4]>,
+}
+
+impl JumpTable {
+    /// whether the table gives the same answers as stepping through `lab`:
+    /// a rectangular map of nothing but terrain, with guards that turn right
+    pub(crate) fn models(lab: &Lab) -> bool {
+        let width = lab.state.first().map_or(0, |row| row.len());
+        let squares = lab.state.iter().all(|row| {
+            row.len() == width
+                && row
+                    .iter()
+                    .all(|square| matches!(square, Square::Empty | Square::Terrain))
+        });
+
+        squares && lab.guards.iter().all(|guard| guard.turn == Turn::Right)
+    }
+
+    pub(crate) fn new(state: &[Vec<Square>]) -> Self {
+        let height = state.len();
+        let width = state[0].len();
+        let blocked = |i: usize, j: usize| matches!(state[i][j], Square::Terrain);
+        let mut stops = vec![[None; 4]; height * width];
+
+        // sweep every row and column from both ends, carrying the latest stop
+        for i in 0..height {
+            let mut stop = None;
+            for j in (0..width).rev() {
+                if blocked(i, j) {
+                    stop = j.checked_sub(1).map(|j| (i, j));
+                } else {
+                    stops[i * width + j][Direction::East.index()] = stop;
+                }
+            }
+
+            let mut stop = None;
+            for j in 0..width {
+                if blocked(i, j) {
+                    stop = (j + 1 < width).then_some((i, j + 1));
+                } else {
+                    stops[i * width + j][Direction::West.index()] = stop;
+                }
+            }
+        }
+
+        for j in 0..width {
+            let mut stop = None;
+            for i in (0..height).rev() {
+                if blocked(i, j) {
+                    stop = i.checked_sub(1).map(|i| (i, j));
+                } else {
+                    stops[i * width + j][Direction::South.index()] = stop;
+                }
+            }
+
+            let mut stop = None;
+            for i in 0..height {
+                if blocked(i, j) {
+                    stop = (i + 1 < height).then_some((i + 1, j));
+                } else {
+                    stops[i * width + j][Direction::North.index()] = stop;
+                }
+            }
+        }
+
+        Self { width, stops }
+    }
+
+    pub(crate) fn stop(&self, (i, j): Cell, direction: Direction) -> Option<Cell> {
+        self.stops[i * self.width + j][direction.index()]
+    }
+
+    /// The table as if there were one more obstacle, at `obstacle`. Only
+    /// stops in its row and column can change, so they're patched on lookup
+    /// instead of copying the table.
+    pub(crate) fn with_obstacle(&self, obstacle: Cell) -> Patched<'_> {
+        Patched {
+            table: self,
+            obstacle,
+        }
+    }
+}
+
+pub(crate) struct Patched<'t> {
+    table: &'t JumpTable,
+    obstacle: Cell,
+}
+
+impl Patched<'_> {
+    pub(crate) fn stop(&self, (i, j): Cell, direction: Direction) -> Option<Cell> {
+        let stop = self.table.stop((i, j), direction);
+        let (y, x) = self.obstacle;
+
+        // the obstacle is on an empty cell, so it's in the way if it's no
+        // further than where the guard would otherwise stop
+        match direction {
+            Direction::North if x == j && y < i && stop.is_none_or(|(s, _)| s <= y) => {
+                Some((y + 1, j))
+            }
+            Direction::South if x == j && y > i && stop.is_none_or(|(s, _)| y <= s) => {
+                Some((y - 1, j))
+            }
+            Direction::West if y == i && x < j && stop.is_none_or(|(_, s)| s <= x) => {
+                Some((i, x + 1))
+            }
+            Direction::East if y == i && x > j && stop.is_none_or(|(_, s)| x <= s) => {
+                Some((i, x - 1))
+            }
+            _ => stop,
+        }
+    }
+
+    /// Whether a guard starting at `start` walks in circles. Only the cells
+    /// where the guard turns are remembered, since a loop has to repeat one.
+    pub(crate) fn loops(&self, start: Cell, direction: Direction) -> bool {
+        let mut turns = std::collections::HashSet::new();
+        let (mut cell, mut direction) = (start, direction);
+
+        loop {
+            let Some(stop) = self.stop(cell, direction) else {
+                return false;
+            };
+            if !turns.insert((stop, direction)) {
+                return true;
+            }
+            (cell, direction) = (stop, direction.turn_right());
+        }
+    }
+}
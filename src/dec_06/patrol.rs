@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use super::simulator::{Cell, Guard, Lab, Outcome, Simulator, Tracking};
+
+/// Two guards running into each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Collision {
+    /// on the same cell after `tick` ticks
+    Meeting {
+        tick: usize,
+        cell: Cell,
+        guards: (usize, usize),
+    },
+    /// swapping cells during the tick that ends at `tick`
+    Crossing {
+        tick: usize,
+        cells: (Cell, Cell),
+        guards: (usize, usize),
+    },
+}
+
+/// How a patrol went, guards indexed as in `Lab::guards`
+pub(crate) struct Report {
+    /// each guard on its own; guards pass through each other, so these
+    /// are the same as if each had the lab to itself
+    pub(crate) outcomes: Vec<Outcome>,
+    /// for the guards together: `Exited` once the last one has left, or
+    /// `Looped` from when the whole patrol starts repeating
+    pub(crate) joint: Outcome,
+    /// every collision within the first `max_ticks`, in order
+    pub(crate) collisions: Vec<Collision>,
+    /// whether `max_ticks` ran out before the joint outcome was reached, so
+    /// there may be collisions that aren't in `collisions`
+    pub(crate) truncated: bool,
+}
+
+/// Every guard in a lab moving at once, a tick at a time
+pub(crate) struct Patrol<'l> {
+    lab: &'l Lab,
+}
+
+impl<'l> Patrol<'l> {
+    pub(crate) fn new(lab: &'l Lab) -> Self {
+        Self { lab }
+    }
+
+    /// Works out each guard's outcome, combines them into the patrol's, then
+    /// replays the guards together to find collisions. After the joint loop
+    /// starts they only repeat, so the replay stops after one period, or at
+    /// `max_ticks`, since the period is the lcm of every looping guard's.
+    pub(crate) fn run(&self, max_ticks: usize) -> Report {
+        let outcomes = self
+            .lab
+            .guards
+            .iter()
+            .map(|guard| Simulator::new(self.lab, *guard, Tracking::Off).run(&mut ()))
+            .collect::<Vec<_>>();
+        let joint = Patrol::joint(&outcomes);
+
+        let horizon = match joint {
+            Outcome::Exited { ticks } => ticks,
+            Outcome::Looped {
+                cycle_start,
+                cycle_len,
+            } => cycle_start.saturating_add(cycle_len),
+        };
+
+        let mut collisions = vec![];
+        let mut guards = self.lab.guards.iter().map(|g| Some(*g)).collect::<Vec<_>>();
+        for tick in 1..=horizon.min(max_ticks) {
+            let next = guards
+                .iter()
+                .map(|guard| guard.and_then(|guard| self.lab.tick(guard)))
+                .collect::<Vec<_>>();
+            Patrol::collide(tick, &guards, &next, &mut collisions);
+            guards = next;
+        }
+
+        Report {
+            outcomes,
+            joint,
+            collisions,
+            truncated: horizon > max_ticks,
+        }
+    }
+
+    /// The patrol as a whole repeats once every guard still on the map is in
+    /// its loop and every other guard has gone. Guards leave the tick after
+    /// their last on the map.
+    fn joint(outcomes: &[Outcome]) -> Outcome {
+        let mut start = 0;
+        let mut period = None;
+
+        for outcome in outcomes {
+            match *outcome {
+                Outcome::Exited { ticks } => start = start.max(ticks + 1),
+                Outcome::Looped {
+                    cycle_start,
+                    cycle_len,
+                } => {
+                    start = start.max(cycle_start);
+                    period = Some(period.map_or(cycle_len, |p| lcm(p, cycle_len)));
+                }
+            }
+        }
+
+        match period {
+            // the start is when the last guard left, so it walked off a tick before
+            None => Outcome::Exited { ticks: start - 1 },
+            Some(cycle_len) => Outcome::Looped {
+                cycle_start: start,
+                cycle_len,
+            },
+        }
+    }
+
+    fn collide(
+        tick: usize,
+        before: &[Option<Guard>],
+        after: &[Option<Guard>],
+        collisions: &mut Vec<Collision>,
+    ) {
+        let mut by_cell: HashMap<Cell, usize> = HashMap::new();
+        for (b, guard) in after.iter().enumerate() {
+            let Some(guard) = guard else {
+                continue;
+            };
+            if let Some(a) = by_cell.insert(guard.cell, b) {
+                collisions.push(Collision::Meeting {
+                    tick,
+                    cell: guard.cell,
+                    guards: (a, b),
+                });
+            }
+        }
+
+        for b in 0..after.len() {
+            for a in 0..b {
+                let (Some(a_from), Some(a_to), Some(b_from), Some(b_to)) =
+                    (before[a], after[a], before[b], after[b])
+                else {
+                    continue;
+                };
+
+                if a_from.cell != a_to.cell && a_from.cell == b_to.cell && a_to.cell == b_from.cell
+                {
+                    collisions.push(Collision::Crossing {
+                        tick,
+                        cells: (a_from.cell, b_from.cell),
+                        guards: (a, b),
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    (a / gcd(a, b)).saturating_mul(b)
+}
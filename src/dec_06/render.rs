@@ -0,0 +1,146 @@
+use std::{collections::HashMap, io::Write, time::Duration};
+
+use super::simulator::{Cell, Guard, Lab, Observer};
+use super::{Direction, Square};
+
+static CLEAR: &str = "\x1b[2J";
+static HOME: &str = "\x1b[H";
+
+/// Which ways the guard has moved through each cell
+#[derive(Default)]
+pub(crate) struct Trail {
+    /// (vertical, horizontal)
+    marks: HashMap<Cell, (bool, bool)>,
+}
+
+impl Trail {
+    fn mark(&mut self, cell: Cell, direction: Direction) {
+        let mark = self.marks.entry(cell).or_default();
+        match direction {
+            Direction::North | Direction::South => mark.0 = true,
+            Direction::East | Direction::West => mark.1 = true,
+        }
+    }
+}
+
+impl Observer for Trail {
+    fn on_step(&mut self, from: Guard, to: Guard) {
+        self.mark(from.cell, from.direction);
+        self.mark(to.cell, to.direction);
+    }
+
+    fn on_turn(&mut self, guard: Guard, to: Direction) {
+        self.mark(guard.cell, guard.direction);
+        self.mark(guard.cell, to);
+    }
+}
+
+fn glyph(direction: Direction) -> char {
+    match direction {
+        Direction::North => '^',
+        Direction::South => 'v',
+        Direction::East => '>',
+        Direction::West => '<',
+    }
+}
+
+/// The lab in the puzzle's notation: the trail drawn with `|` and `-`, and
+/// `+` where it turns or crosses itself, `guard` drawn over it, and
+/// `obstacle` as `O`
+pub(crate) fn render(
+    lab: &Lab,
+    trail: &Trail,
+    guard: Option<Guard>,
+    obstacle: Option<Cell>,
+) -> String {
+    let mut out = String::new();
+    for (i, row) in lab.state.iter().enumerate() {
+        for (j, square) in row.iter().enumerate() {
+            let c = match (guard, trail.marks.get(&(i, j)), square) {
+                _ if obstacle == Some((i, j)) => 'O',
+                (Some(guard), _, _) if guard.cell == (i, j) => glyph(guard.direction),
+                (_, Some((true, true)), _) => '+',
+                (_, Some((true, false)), _) => '|',
+                (_, Some((false, true)), _) => '-',
+                (_, _, Square::Guard(direction)) => glyph(*direction),
+                (_, _, Square::Empty) => '.',
+                (_, _, Square::Terrain) => '#',
+                (_, _, Square::Door(way)) => match way {
+                    Direction::North => 'N',
+                    Direction::South => 'S',
+                    Direction::East => 'E',
+                    Direction::West => 'W',
+                },
+                (_, _, Square::Teleporter(label)) => *label as char,
+            };
+            out.push(c);
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Draws a frame to `out` at every tick of a run, `delay` apart, clearing
+/// the terminal in between
+pub(crate) struct Animation<'l, W: Write> {
+    lab: &'l Lab,
+    obstacle: Option<Cell>,
+    trail: Trail,
+    out: W,
+    delay: Duration,
+    error: Option<std::io::Error>,
+}
+
+impl<'l, W: Write> Animation<'l, W> {
+    pub(crate) fn new(
+        lab: &'l Lab,
+        obstacle: Option<Cell>,
+        out: W,
+        frames_per_second: u32,
+    ) -> Self {
+        Self {
+            lab,
+            obstacle,
+            trail: Trail::default(),
+            out,
+            delay: Duration::from_secs(1)
+                .checked_div(frames_per_second)
+                .unwrap_or_default(),
+            error: None,
+        }
+    }
+
+    /// The first error writing a frame. Frames stop being drawn after one.
+    pub(crate) fn take_error(&mut self) -> Option<std::io::Error> {
+        self.error.take()
+    }
+
+    fn frame(&mut self, guard: Guard) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let frame = render(self.lab, &self.trail, Some(guard), self.obstacle);
+        let written = write!(self.out, "{HOME}{CLEAR}{frame}").and_then(|()| self.out.flush());
+        match written {
+            Ok(()) => std::thread::sleep(self.delay),
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
+
+impl<W: Write> Observer for Animation<'_, W> {
+    fn on_step(&mut self, from: Guard, to: Guard) {
+        self.trail.on_step(from, to);
+        self.frame(to);
+    }
+
+    fn on_turn(&mut self, guard: Guard, to: Direction) {
+        self.trail.on_turn(guard, to);
+        self.frame(Guard {
+            direction: to,
+            ..guard
+        });
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Direction, Square};
+
+pub(crate) type Cell = (usize, usize);
+
+/// Which way a guard turns when something's in its way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub(crate) enum Turn {
+    Left,
+    #[default]
+    Right,
+    Reverse,
+}
+
+impl Turn {
+    pub(crate) fn apply(self, direction: Direction) -> Direction {
+        match self {
+            Turn::Left => direction.turn_right().turn_right().turn_right(),
+            Turn::Right => direction.turn_right(),
+            Turn::Reverse => direction.turn_right().turn_right(),
+        }
+    }
+}
+
+/// Where a guard is, which way it's facing, and how it turns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Guard {
+    pub(crate) cell: Cell,
+    pub(crate) direction: Direction,
+    pub(crate) turn: Turn,
+}
+
+/// The map, with the guards lifted off it into `guards`
+#[derive(Clone)]
+pub(crate) struct Lab {
+    pub(crate) state: Vec<Vec<Square>>,
+    pub(crate) guards: Vec<Guard>,
+    /// each teleporter's cell, mapped to its twin's
+    twins: HashMap<Cell, Cell>,
+}
+
+impl Lab {
+    /// Fails on a character that isn't a square, on a map with no guards,
+    /// or on a teleporter without exactly one twin
+    pub(crate) fn load_from_string(input: &str) -> Result<Self, String> {
+        let mut state = vec![];
+        let mut guards = vec![];
+        let mut teleporters: HashMap<u8, Vec<Cell>> = HashMap::new();
+        for (i, line) in input.lines().enumerate() {
+            let mut next = vec![];
+            for (j, cell) in line.as_bytes().iter().enumerate() {
+                let Some(mut square) = Square::parse(cell) else {
+                    return Err(format!(
+                        "unexpected `{}` at line {}, column {}",
+                        *cell as char,
+                        i + 1,
+                        j + 1
+                    ));
+                };
+                match square {
+                    Square::Guard(direction) => {
+                        guards.push(Guard {
+                            cell: (i, j),
+                            direction,
+                            turn: Turn::default(),
+                        });
+                        square = Square::Empty;
+                    }
+                    Square::Teleporter(label) => teleporters.entry(label).or_default().push((i, j)),
+                    _ => {}
+                }
+                next.push(square);
+            }
+
+            state.push(next);
+        }
+
+        if guards.is_empty() {
+            return Err("there are no guards on the map".to_string());
+        }
+
+        let mut twins = HashMap::new();
+        for (label, cells) in teleporters {
+            let [a, b] = cells[..] else {
+                return Err(format!(
+                    "teleporter `{}` appears {} times, not twice",
+                    label as char,
+                    cells.len()
+                ));
+            };
+            twins.insert(a, b);
+            twins.insert(b, a);
+        }
+
+        Ok(Self {
+            state,
+            guards,
+            twins,
+        })
+    }
+
+    /// the first guard, which is the only one in the puzzle itself
+    pub(crate) fn guard(&self) -> Guard {
+        self.guards[0]
+    }
+
+    /// The guard's next move: a turn if something's in the way, otherwise a
+    /// step forward, and through a teleporter if it steps onto one. `None`
+    /// once it walks off the map, which on ragged rows includes stepping past
+    /// the end of a shorter row.
+    pub(crate) fn tick(&self, guard: Guard) -> Option<Guard> {
+        let (i, j) = guard.cell;
+        let ahead = match guard.direction {
+            Direction::North => (i.checked_sub(1)?, j),
+            Direction::South => (i + 1, j),
+            Direction::East => (i, j + 1),
+            Direction::West => (i, j.checked_sub(1)?),
+        };
+        let square = self.state.get(ahead.0).and_then(|row| row.get(ahead.1))?;
+
+        Some(match *square {
+            Square::Terrain => Guard {
+                direction: guard.turn.apply(guard.direction),
+                ..guard
+            },
+            Square::Door(way) if way != guard.direction => Guard {
+                direction: guard.turn.apply(guard.direction),
+                ..guard
+            },
+            Square::Teleporter(_) => Guard {
+                cell: self.twins[&ahead],
+                ..guard
+            },
+            _ => Guard {
+                cell: ahead,
+                ..guard
+            },
+        })
+    }
+}
+
+/// Hooks into a run, each called as it happens. All default to doing nothing.
+pub(crate) trait Observer {
+    fn on_step(&mut self, _from: Guard, _to: Guard) {}
+    fn on_turn(&mut self, _guard: Guard, _to: Direction) {}
+    /// with the guard as it was on the last cell of the map
+    fn on_exit(&mut self, _guard: Guard) {}
+    /// with the guard as it was when the repeat was noticed
+    fn on_loop(&mut self, _guard: Guard) {}
+}
+
+impl Observer for () {}
+
+/// What the simulator remembers of where the guard has been
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tracking {
+    /// nothing; loops are found with Brent's algorithm instead
+    Off,
+    /// every cell visited, and loops found with Brent's algorithm
+    Cells,
+    /// every cell and heading visited, and the tick it was first seen, which
+    /// catches a loop the first time a state comes round again
+    States,
+}
+
+/// How a run ended. Ticks count both steps and turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    /// the guard walked off the map after `ticks` ticks on it
+    Exited { ticks: usize },
+    /// after `cycle_start` ticks, the guard repeats the same `cycle_len` ticks forever
+    Looped {
+        cycle_start: usize,
+        cycle_len: usize,
+    },
+}
+
+/// Runs one guard around a lab
+pub(crate) struct Simulator<'l> {
+    lab: &'l Lab,
+    start: Guard,
+    tracking: Tracking,
+    cells: HashSet<Cell>,
+    states: HashMap<Guard, usize>,
+}
+
+impl<'l> Simulator<'l> {
+    pub(crate) fn new(lab: &'l Lab, start: Guard, tracking: Tracking) -> Self {
+        Self {
+            lab,
+            start,
+            tracking,
+            cells: HashSet::new(),
+            states: HashMap::new(),
+        }
+    }
+
+    /// Every cell the guard stood on in the last run, if it was tracked
+    pub(crate) fn visited_cells(&self) -> HashSet<Cell> {
+        match self.tracking {
+            Tracking::Off => HashSet::new(),
+            Tracking::Cells => self.cells.clone(),
+            Tracking::States => self.states.keys().map(|guard| guard.cell).collect(),
+        }
+    }
+
+    pub(crate) fn run(&mut self, observer: &mut impl Observer) -> Outcome {
+        self.cells.clear();
+        self.states.clear();
+
+        let mut guard = self.start;
+        self.visit(guard, 0);
+
+        // Brent's algorithm: `lam` is how far the guard has got since the
+        // `tortoise` was last moved up to it, which happens at each power of two
+        let (mut tortoise, mut power, mut lam) = (guard, 1, 0);
+
+        for tick in 1.. {
+            let Some(next) = self.lab.tick(guard) else {
+                observer.on_exit(guard);
+                return Outcome::Exited { ticks: tick - 1 };
+            };
+
+            if next.cell == guard.cell {
+                observer.on_turn(guard, next.direction);
+            } else {
+                observer.on_step(guard, next);
+            }
+            guard = next;
+
+            if let Some(first) = self.visit(guard, tick) {
+                observer.on_loop(guard);
+                return Outcome::Looped {
+                    cycle_start: first,
+                    cycle_len: tick - first,
+                };
+            }
+
+            lam += 1;
+            if self.tracking != Tracking::States && guard == tortoise {
+                observer.on_loop(guard);
+                return Outcome::Looped {
+                    cycle_start: self.cycle_start(lam),
+                    cycle_len: lam,
+                };
+            }
+            if lam == power {
+                (tortoise, power, lam) = (guard, power * 2, 0);
+            }
+        }
+
+        unreachable!()
+    }
+
+    /// records the guard at `tick`, returning when it was first seen in this
+    /// state if it's been here before and states are tracked
+    fn visit(&mut self, guard: Guard, tick: usize) -> Option<usize> {
+        match self.tracking {
+            Tracking::Off => None,
+            Tracking::Cells => {
+                self.cells.insert(guard.cell);
+                None
+            }
+            Tracking::States => match self.states.insert(guard, tick) {
+                Some(first) => {
+                    self.states.insert(guard, first);
+                    Some(first)
+                }
+                None => None,
+            },
+        }
+    }
+
+    /// the first tick of the cycle, by walking two guards `cycle_len` apart
+    /// from the start until they meet
+    fn cycle_start(&self, cycle_len: usize) -> usize {
+        let tick = |guard| {
+            self.lab
+                .tick(guard)
+                .expect("looping guards stay on the map")
+        };
+
+        let mut ahead = self.start;
+        for _ in 0..cycle_len {
+            ahead = tick(ahead);
+        }
+
+        let mut behind = self.start;
+        let mut start = 0;
+        while behind != ahead {
+            (behind, ahead) = (tick(behind), tick(ahead));
+            start += 1;
+        }
+
+        start
+    }
+}
@@ -1,190 +1,494 @@
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, One, Zero};
 use rayon::prelude::*;
-use std::{
-    error::Error,
-    sync::{Arc, Mutex},
-};
+use std::{error::Error, fmt::Display, str::FromStr};
 
 pub fn puzzle1() -> Result<u64, Box<dyn std::error::Error>> {
-    handle_puzzle::<Puzzle1Ops>(std::fs::read_to_string("./inputs/dec07.txt")?.as_str())
+    handle_puzzle::<Puzzle1Ops, u64>(std::fs::read_to_string("./inputs/dec07.txt")?.as_str())
 }
 
 pub fn puzzle2() -> Result<u64, Box<dyn std::error::Error>> {
-    handle_puzzle::<Puzzle2Ops>(std::fs::read_to_string("./inputs/dec07.txt")?.as_str())
+    handle_puzzle::<Puzzle2Ops, u64>(std::fs::read_to_string("./inputs/dec07.txt")?.as_str())
 }
 
-/// A permutation generator over some set of operations and operands
-struct Perms<O>
+/// Prints a witness for each equation that `ops`, the operators of `puzzle1`,
+/// `puzzle2` or `all` of them, can make true, and how many operator sequences do
+pub fn explain(ops: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let input = std::fs::read_to_string("./inputs/dec07.txt")?;
+    match ops {
+        "puzzle1" => explain_with::<Puzzle1Ops>(&input),
+        "puzzle2" => explain_with::<Puzzle2Ops>(&input),
+        "all" => explain_with::<AllOps>(&input),
+        _ => Err(format!("unknown operators `{ops}`").into()),
+    }
+}
+
+fn explain_with<O: OpsSet>(input: &str) -> Result<(), Box<dyn std::error::Error>> {
+    for line in input.lines() {
+        let (expected, operands) = line
+            .split_once(": ")
+            .ok_or(format!("`{line}` has no `: `"))?;
+        let expected = u64::parse(expected).ok_or(format!("`{expected}` doesn't fit"))?;
+        let solver = Solver::<O>::new(operands)?;
+        if let Some(equation) = solver.solve(expected) {
+            println!("{equation}  (solutions: {})", solver.count(expected));
+        }
+    }
+
+    Ok(())
+}
+
+/// The numbers equations are worked in: `u64` for the puzzle, `u128` or
+/// `BigUint` when the operands get big. Anything that overflows doesn't count.
+trait Value:
+    Clone
+    + Ord
+    + Display
+    + Send
+    + Sync
+    + Zero
+    + One
+    + From<u32>
+    + CheckedAdd
+    + CheckedSub
+    + CheckedMul
+    + CheckedDiv
+    + std::ops::Rem<Output = Self>
+{
+    fn parse(s: &str) -> Option<Self>;
+
+    /// `BigUint` has no `CheckedRem`
+    fn checked_rem(&self, other: &Self) -> Option<Self> {
+        match other.is_zero() {
+            true => None,
+            false => Some(self.clone() % other.clone()),
+        }
+    }
+}
+
+impl<T> Value for T
+where
+    T: Clone
+        + Ord
+        + Display
+        + Send
+        + Sync
+        + Zero
+        + One
+        + From<u32>
+        + CheckedAdd
+        + CheckedSub
+        + CheckedMul
+        + CheckedDiv
+        + std::ops::Rem<Output = Self>
+        + FromStr,
+{
+    fn parse(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+}
+
+fn parse_operands<V: Value>(base_str: &str) -> Result<Vec<V>, String> {
+    base_str
+        .split_whitespace()
+        .map(|num| V::parse(num).ok_or(format!("`{num}` doesn't fit")))
+        .collect()
+}
+
+/// A permutation generator over some set of operations and operands, kept as
+/// the brute force `Solver` is tested against
+#[cfg(test)]
+struct Perms<O, V = u64>
 where
     O: OpsSet,
 {
     /// The nums that must be searched for a companion
-    nums: Vec<u64>,
-    ///
+    nums: Vec<V>,
+    /// The op ids of the permutation to yield next
     ops_perm: Option<Vec<usize>>,
     _marker: std::marker::PhantomData<O>,
 }
 
-impl<O> Perms<O>
+#[cfg(test)]
+impl<O, V> Perms<O, V>
 where
     O: OpsSet,
+    V: Value,
 {
-    pub fn new(base_str: &str) -> Self {
-        let nums = base_str
-            .split_whitespace()
-            .map(|num| num.parse().unwrap())
-            .collect::<Vec<_>>();
+    pub fn new(base_str: &str) -> Result<Self, String> {
+        O::check()?;
+        let nums = parse_operands(base_str)?;
         let ops_perm = Some(vec![0; nums.len() - 1]);
 
-        Self {
+        Ok(Self {
             nums,
             ops_perm,
             _marker: std::marker::PhantomData,
-        }
+        })
     }
 
-    /// Compute the product of the current permutation
-    pub fn compute(&self) -> Option<u64>
-    where
-        O: OpsSet,
-    {
-        if let Some(ref ops_perm) = self.ops_perm {
-            Some(
-                self.nums
-                    .iter()
-                    .skip(1)
-                    .zip(ops_perm)
-                    .fold(self.nums[0], |acc, (num, op)| O::apply(*op, acc, *num)),
-            )
-        } else {
-            None
-        }
+    /// Compute the product of a permutation, `None` if it overflows
+    pub fn compute(&self, ops_perm: &[usize]) -> Option<V> {
+        self.nums
+            .iter()
+            .skip(1)
+            .zip(ops_perm)
+            .try_fold(self.nums[0].clone(), |acc, (num, op)| {
+                O::apply(*op, &acc, num)
+            })
     }
 }
 
-impl<O> Iterator for Perms<O>
+#[cfg(test)]
+impl<O, V> Iterator for Perms<O, V>
 where
     O: OpsSet,
+    V: Value,
 {
-    type Item = u64;
+    type Item = Option<V>;
 
     /// Iterate all x^k permutations, where x := |ops_set|, k := |operands_list|
-    fn next(&mut self) -> Option<Self::Item>
-    where
-        O: OpsSet,
-    {
-        if let Some(result) = self.compute() {
-            let Some(ref mut ops_perm) = self.ops_perm else {
-                panic!()
-            };
-            let mut i = 0_usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.compute(self.ops_perm.as_ref()?);
 
-            loop {
-                ops_perm[i] = (ops_perm[i] + 1) % O::len();
-                if ops_perm[i] != 0 {
-                    break;
-                }
-                i += 1;
-                if i >= ops_perm.len() {
-                    self.ops_perm = None;
-                    break;
+        let Some(ref mut ops_perm) = self.ops_perm else {
+            panic!()
+        };
+        let mut i = 0_usize;
+
+        loop {
+            ops_perm[i] = (ops_perm[i] + 1) % O::len();
+            if ops_perm[i] != 0 {
+                break;
+            }
+            i += 1;
+            if i >= ops_perm.len() {
+                self.ops_perm = None;
+                break;
+            }
+        }
+
+        Some(result)
+    }
+}
+
+/// What the left operand of an operation must have been, given its result
+/// and right operand
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Inverse<V> {
+    /// no left operand gives that result
+    Never,
+    Exactly(V),
+    /// there's no single answer: any left operand works for `a * 0`, and
+    /// infinitely many for `a % b`, so the solver tries what's before it forwards
+    Unknown,
+}
+
+/// Searches operator sequences from the right: the last operator and operand
+/// pin down what everything before them must evaluate to, so most branches
+/// die as soon as an operator can't be undone (a `*` that doesn't divide, a
+/// `+` that would go negative, a `||` whose digits don't match).
+struct Solver<O, V = u64>
+where
+    O: OpsSet,
+{
+    nums: Vec<V>,
+    _marker: std::marker::PhantomData<O>,
+}
+
+impl<O, V> Solver<O, V>
+where
+    O: OpsSet,
+    V: Value,
+{
+    pub fn new(base_str: &str) -> Result<Self, String> {
+        O::check()?;
+        Ok(Self {
+            nums: parse_operands(base_str)?,
+            _marker: std::marker::PhantomData,
+        })
+    }
+
+    /// The first operator sequence found that makes the operands evaluate to
+    /// `target`
+    pub fn solve(&self, target: V) -> Option<Equation<O, V>> {
+        let mut ops = vec![0; self.nums.len() - 1];
+        (self.backward(self.nums.len() - 1, &target, &mut ops, true) > 0).then(|| Equation {
+            nums: self.nums.clone(),
+            ops,
+            target,
+            _marker: std::marker::PhantomData,
+        })
+    }
+
+    /// How many operator sequences make the operands evaluate to `target`
+    pub fn count(&self, target: V) -> u64 {
+        let mut ops = vec![0; self.nums.len() - 1];
+        self.backward(self.nums.len() - 1, &target, &mut ops, false)
+    }
+
+    /// How many ways `nums[..=i]` can evaluate to `target`. With `first_only`
+    /// it stops at the first, leaving it in `ops[..i]`.
+    fn backward(&self, i: usize, target: &V, ops: &mut [usize], first_only: bool) -> u64 {
+        if i == 0 {
+            return (self.nums[0] == *target) as u64;
+        }
+
+        let mut found = 0_u64;
+        for op in 0..O::len() {
+            let b = &self.nums[i];
+            found += match O::unapply(op, target, b) {
+                Inverse::Never => 0,
+                Inverse::Exactly(a) => self.backward(i - 1, &a, ops, first_only),
+                Inverse::Unknown => {
+                    let accept = |a: &V| O::apply(op, a, b).as_ref() == Some(target);
+                    self.forward(0, self.nums[0].clone(), i - 1, ops, &accept, first_only)
                 }
+            };
+
+            if first_only && found > 0 {
+                ops[i - 1] = op;
+                return found;
             }
+        }
 
-            Some(result)
-        } else {
-            None
+        found
+    }
+
+    /// How many ways of filling `ops[j..end]` take `acc`, the value of
+    /// `nums[..=j]`, to a value of `nums[..=end]` that `accept`s
+    fn forward(
+        &self,
+        j: usize,
+        acc: V,
+        end: usize,
+        ops: &mut [usize],
+        accept: &dyn Fn(&V) -> bool,
+        first_only: bool,
+    ) -> u64 {
+        if j == end {
+            return accept(&acc) as u64;
         }
+
+        let mut found = 0_u64;
+        for op in 0..O::len() {
+            let Some(next) = O::apply(op, &acc, &self.nums[j + 1]) else {
+                continue;
+            };
+            found += self.forward(j + 1, next, end, ops, accept, first_only);
+
+            if first_only && found > 0 {
+                ops[j] = op;
+                return found;
+            }
+        }
+
+        found
     }
 }
 
-/// A set of operations
-trait OpsSet {
-    /// Perform the computation
-    fn apply(op_id: usize, a: u64, b: u64) -> u64;
+/// Operands with the operators between them that evaluate, left to right,
+/// to `target`
+struct Equation<O, V = u64>
+where
+    O: OpsSet,
+{
+    pub nums: Vec<V>,
+    /// op ids, `ops[i]` going between `nums[i]` and `nums[i + 1]`
+    pub ops: Vec<usize>,
+    pub target: V,
+    _marker: std::marker::PhantomData<O>,
+}
 
-    /// Get # of ops
-    fn len() -> usize;
+impl<O, V> Equation<O, V>
+where
+    O: OpsSet,
+    V: Value,
+{
+    /// the left hand side, strictly left to right as the puzzle has it
+    #[cfg(test)]
+    pub fn evaluate(&self) -> Option<V> {
+        self.nums
+            .iter()
+            .skip(1)
+            .zip(&self.ops)
+            .try_fold(self.nums[0].clone(), |acc, (num, op)| {
+                O::apply(*op, &acc, num)
+            })
+    }
 }
-#[derive(Clone, Copy)]
-struct Puzzle1Ops;
-unsafe impl Send for Puzzle1Ops {}
-const OPS_PUZZLE1: [char; 2] = ['+', '*'];
-impl OpsSet for Puzzle1Ops {
-    fn apply(op_id: usize, a: u64, b: u64) -> u64 {
-        match OPS_PUZZLE1[op_id] {
-            '+' => a + b,
-            '*' => a * b,
-            _ => unreachable!(),
+
+impl<O, V> std::fmt::Display for Equation<O, V>
+where
+    O: OpsSet,
+    V: Value,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.nums[0])?;
+        for (num, op) in self.nums.iter().skip(1).zip(&self.ops) {
+            write!(f, " {} {num}", O::OPS[*op])?;
         }
+        write!(f, " = {}", self.target)
     }
+}
 
-    fn len() -> usize {
-        OPS_PUZZLE1.len()
-    }
+/// One binary operation, described by what it is rather than by a symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Mul,
+    Sub,
+    Rem,
+    /// writes `b`'s digits after `a`'s, in the given base
+    Concat(u32),
 }
 
-#[derive(Clone, Copy)]
+impl Operator {
+    pub fn apply<V: Value>(self, a: &V, b: &V) -> Option<V> {
+        match self {
+            Operator::Add => a.checked_add(b),
+            Operator::Mul => a.checked_mul(b),
+            Operator::Sub => a.checked_sub(b),
+            Operator::Rem => a.checked_rem(b),
+            Operator::Concat(base) => a.checked_mul(&Operator::shift(base, b)?)?.checked_add(b),
+        }
+    }
 
-struct Puzzle2Ops;
-unsafe impl Send for Puzzle2Ops {}
-const OPS_PUZZLE2: [char; 3] = ['+', '*', '|'];
-impl OpsSet for Puzzle2Ops {
-    fn apply(op_id: usize, a: u64, mut b: u64) -> u64 {
-        match OPS_PUZZLE2[op_id] {
-            '+' => a + b,
-            '*' => a * b,
-            '|' => {
-                let mut exp = 0_u32;
-                let mut product = 0_u64;
-
-                while b != 0 {
-                    product += (b % 10) * 10u64.pow(exp);
-                    exp += 1;
-                    b /= 10;
+    /// which `a` gives `apply(a, b) == target`
+    pub fn unapply<V: Value>(self, target: &V, b: &V) -> Inverse<V> {
+        let exactly = |a: Option<V>| match a {
+            Some(a) => Inverse::Exactly(a),
+            None => Inverse::Never,
+        };
+
+        match self {
+            Operator::Add => exactly(target.checked_sub(b)),
+            Operator::Sub => exactly(target.checked_add(b)),
+            Operator::Mul if b.is_zero() => match target.is_zero() {
+                true => Inverse::Unknown,
+                false => Inverse::Never,
+            },
+            Operator::Mul => match target.checked_rem(b).is_some_and(|r| r.is_zero()) {
+                true => exactly(target.checked_div(b)),
+                false => Inverse::Never,
+            },
+            Operator::Rem if *target < *b => Inverse::Unknown,
+            Operator::Rem => Inverse::Never,
+            Operator::Concat(base) => {
+                // a shift that overflows can't have been applied
+                let Some(shift) = Operator::shift(base, b) else {
+                    return Inverse::Never;
+                };
+                match target.checked_rem(&shift).as_ref() == Some(b) {
+                    true => exactly(target.checked_div(&shift)),
+                    false => Inverse::Never,
                 }
+            }
+        }
+    }
 
-                product += a * 10u64.pow(exp);
+    /// `base` to the power of how many digits `b` has, counting 0 as one digit
+    fn shift<V: Value>(base: u32, b: &V) -> Option<V> {
+        let base = V::from(base);
+        let mut shift = base.clone();
+        let mut rest = b.checked_div(&base)?;
+        while !rest.is_zero() {
+            shift = shift.checked_mul(&base)?;
+            rest = rest.checked_div(&base)?;
+        }
 
-                product
-            }
-            _ => unreachable!(),
+        Some(shift)
+    }
+}
+
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Mul => write!(f, "*"),
+            Operator::Sub => write!(f, "-"),
+            Operator::Rem => write!(f, "%"),
+            Operator::Concat(10) => write!(f, "||"),
+            Operator::Concat(base) => write!(f, "||{base}"),
         }
     }
+}
+
+/// A set of operations, indexed by op id
+trait OpsSet {
+    const OPS: &'static [Operator];
+
+    /// Perform the computation, `None` if it overflows
+    fn apply<V: Value>(op_id: usize, a: &V, b: &V) -> Option<V> {
+        Self::OPS[op_id].apply(a, b)
+    }
 
+    /// Undo the computation: which `a` gives `apply(op_id, a, b) == target`
+    fn unapply<V: Value>(op_id: usize, target: &V, b: &V) -> Inverse<V> {
+        Self::OPS[op_id].unapply(target, b)
+    }
+
+    /// Get # of ops
     fn len() -> usize {
-        OPS_PUZZLE2.len()
+        Self::OPS.len()
+    }
+
+    /// Fails on an operator that can't be applied, like `||` in a base below 2
+    fn check() -> Result<(), String> {
+        match Self::OPS
+            .iter()
+            .find(|op| matches!(op, Operator::Concat(0 | 1)))
+        {
+            Some(op) => Err(format!("`{op}` needs a base of at least 2")),
+            None => Ok(()),
+        }
     }
 }
 
+struct Puzzle1Ops;
+impl OpsSet for Puzzle1Ops {
+    const OPS: &'static [Operator] = &[Operator::Add, Operator::Mul];
+}
+
+struct Puzzle2Ops;
+impl OpsSet for Puzzle2Ops {
+    const OPS: &'static [Operator] = &[Operator::Add, Operator::Mul, Operator::Concat(10)];
+}
+
+/// Every operator there is, with `||` in base 10
+struct AllOps;
+impl OpsSet for AllOps {
+    const OPS: &'static [Operator] = &[
+        Operator::Add,
+        Operator::Mul,
+        Operator::Sub,
+        Operator::Rem,
+        Operator::Concat(10),
+    ];
+}
+
 /// Run the puzzle for some input and OpsSet
-fn handle_puzzle<O>(input: &str) -> Result<u64, Box<dyn Error>>
+fn handle_puzzle<O, V>(input: &str) -> Result<V, Box<dyn Error>>
 where
-    O: OpsSet + Send,
+    O: OpsSet,
+    V: Value,
 {
-    let lines = input.lines();
-    let answers: Arc<Mutex<Vec<Option<u64>>>> =
-        Arc::new(Mutex::new(vec![None; lines.clone().count()]));
-    lines
-        .enumerate()
+    let solvable = input
+        .lines()
         .par_bridge()
-        .flat_map(|(index, line)| {
+        .map(|line| {
             let (expected, operands) = line.split_once(": ").unwrap();
-            let expected = expected.parse::<u64>().unwrap();
-            Perms::<O>::new(operands)
-                .map(move |actual| (index, expected, actual))
-                // take only the successful solutions
-                .filter(|(_, expected, actual)| expected == actual)
-                .par_bridge()
+            let expected = V::parse(expected).ok_or(format!("`{expected}` doesn't fit"))?;
+            Ok(Solver::<O, V>::new(operands)?
+                .solve(expected.clone())
+                .map(|_| expected))
         })
-        // now, all permutations of all lines are running in parallel
-        .for_each(|(index, _, actual)| answers.lock().unwrap()[index] = Some(actual));
+        .collect::<Result<Vec<_>, String>>()?;
 
-    let result = answers
-        .lock()
-        .unwrap()
+    let result = solvable
         .iter()
-        .filter_map(|e| e.map(|actual| actual))
-        .sum();
+        .flatten()
+        .try_fold(V::zero(), |acc, x| acc.checked_add(x))
+        .ok_or("the sum overflowed")?;
 
     Ok(result)
 }
@@ -201,7 +505,7 @@ fn test_puzzle1() -> Result<(), Box<dyn std::error::Error>> {
 21037: 9 7 18 13
 292: 11 6 16 20"#;
 
-    assert_eq!(handle_puzzle::<Puzzle1Ops>(input)?, 3749);
+    assert_eq!(handle_puzzle::<Puzzle1Ops, u64>(input)?, 3749);
 
     Ok(())
 }
@@ -218,7 +522,206 @@ fn test_puzzle2() -> Result<(), Box<dyn std::error::Error>> {
 21037: 9 7 18 13
 292: 11 6 16 20"#;
 
-    assert_eq!(handle_puzzle::<Puzzle2Ops>(input)?, 11387);
+    assert_eq!(handle_puzzle::<Puzzle2Ops, u64>(input)?, 11387);
+
+    Ok(())
+}
+
+#[test]
+fn test_solver_matches_perms() -> Result<(), Box<dyn std::error::Error>> {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // including the operators that can't always be undone
+    struct Everything;
+    impl OpsSet for Everything {
+        const OPS: &'static [Operator] = &[
+            Operator::Add,
+            Operator::Mul,
+            Operator::Sub,
+            Operator::Rem,
+            Operator::Concat(2),
+        ];
+    }
+
+    fn check<O: OpsSet>(operands: &str, target: u64) -> Result<(), String> {
+        let solved = Solver::<O>::new(operands)?.solve(target);
+        let brute_force = Perms::<O>::new(operands)?.any(|actual| actual == Some(target));
+        assert_eq!(solved.is_some(), brute_force, "{target}: {operands}");
+
+        // the witness really does evaluate to the target
+        if let Some(equation) = solved {
+            assert_eq!(equation.evaluate(), Some(target), "{equation}");
+        }
+
+        let count = Perms::<O>::new(operands)?
+            .filter(|actual| *actual == Some(target))
+            .count();
+        assert_eq!(
+            Solver::<O>::new(operands)?.count(target),
+            count as u64,
+            "{target}: {operands}"
+        );
+
+        Ok(())
+    }
+
+    let mut rng = StdRng::seed_from_u64(0x2024_1207);
+    let mut random = move |bound: u64| rng.gen_range(0..bound);
+
+    for _ in 0..2000 {
+        let len = 2 + random(4) as usize;
+        let nums = (0..len).map(|_| random(20)).collect::<Vec<_>>();
+        let operands = nums
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // half the time, a target that's reachable by construction
+        let target = if random(2) == 0 {
+            random(5000)
+        } else {
+            let ops = (1..len).map(|_| random(3) as usize).collect::<Vec<_>>();
+            nums[1..]
+                .iter()
+                .zip(ops)
+                .try_fold(nums[0], |acc, (num, op)| Puzzle2Ops::apply(op, &acc, num))
+                .unwrap()
+        };
+
+        check::<Puzzle1Ops>(&operands, target)?;
+        check::<Puzzle2Ops>(&operands, target)?;
+        check::<Everything>(&operands, target)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_witness() -> Result<(), Box<dyn std::error::Error>> {
+    let solver = Solver::<Puzzle1Ops>::new("81 40 27")?;
+    assert_eq!(
+        solver.solve(3267).map(|e| e.to_string()),
+        Some("81 * 40 + 27 = 3267".to_string())
+    );
+    // and 81 + 40 * 27
+    assert_eq!(solver.count(3267), 2);
+    assert_eq!(solver.count(3268), 0);
+    assert!(solver.solve(3268).is_none());
+
+    let solver = Solver::<Puzzle2Ops>::new("6 8 6 15")?;
+    assert_eq!(
+        solver.solve(7290).map(|e| e.to_string()),
+        Some("6 * 8 || 6 * 15 = 7290".to_string())
+    );
+
+    // multiplying by 0 at the end works whatever comes before it
+    assert_eq!(Solver::<Puzzle2Ops>::new("1 2 3 0")?.count(0), 9);
+
+    Ok(())
+}
+
+#[test]
+fn test_overflow() -> Result<(), Box<dyn std::error::Error>> {
+    use num_bigint::BigUint;
+
+    // 2^32 * 2^32 doesn't fit in a u64, so that sequence just doesn't count
+    let operands = "4294967296 4294967296";
+    assert_eq!(
+        Perms::<Puzzle1Ops, u64>::new(operands)?.collect::<Vec<_>>(),
+        vec![Some(1 << 33), None]
+    );
+    assert_eq!(
+        Perms::<Puzzle1Ops, u128>::new(operands)?.collect::<Vec<_>>(),
+        vec![Some(1 << 33), Some(1 << 64)]
+    );
+    assert_eq!(Solver::<Puzzle2Ops, u64>::new("99999 99999")?.count(0), 0);
+
+    // the same in u128 and past it, concatenating up past 2^128
+    let input = "18446744073709551616: 4294967296 4294967296\n\
+        1844674407370955161618446744073709551616: 18446744073709551616 18446744073709551616";
+    assert_eq!(
+        handle_puzzle::<Puzzle2Ops, u128>("18446744073709551616: 4294967296 4294967296")?,
+        1 << 64
+    );
+    assert!(handle_puzzle::<Puzzle2Ops, u128>(input).is_err());
+    assert_eq!(
+        handle_puzzle::<Puzzle2Ops, BigUint>(input)?.to_string(),
+        "1844674407370955161636893488147419103232"
+    );
+
+    let solver = Solver::<Puzzle2Ops, BigUint>::new("18446744073709551616 18446744073709551616")?;
+    assert_eq!(
+        solver
+            .solve("1844674407370955161618446744073709551616".parse()?)
+            .map(|e| e.to_string()),
+        Some(
+            "18446744073709551616 || 18446744073709551616 = 1844674407370955161618446744073709551616"
+                .to_string()
+        )
+    );
+
+    // so do the operands, not just the target
+    assert_eq!(
+        handle_puzzle::<Puzzle1Ops, u64>("3: 18446744073709551616 1")
+            .unwrap_err()
+            .to_string(),
+        "`18446744073709551616` doesn't fit"
+    );
+    assert!(handle_puzzle::<Puzzle1Ops, u128>("3: 18446744073709551616 1").is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_operators() -> Result<(), Box<dyn std::error::Error>> {
+    struct Custom;
+    impl OpsSet for Custom {
+        const OPS: &'static [Operator] = &[Operator::Sub, Operator::Rem, Operator::Concat(2)];
+    }
+
+    // 5 ||2 3 is 0b101 then 0b11
+    assert_eq!(Custom::apply(2, &5_u64, &3), Some(0b10111));
+    assert_eq!(Custom::apply(0, &3_u64, &5), None);
+    assert_eq!(Custom::apply(1, &3_u64, &0), None);
+
+    // 0 is written as one digit, so `12 || 0` is 120 rather than 12
+    let concat = Operator::Concat(10);
+    assert_eq!(concat.apply(&12_u64, &0), Some(120));
+    assert_eq!(concat.apply(&0_u64, &0), Some(0));
+    assert_eq!(concat.unapply(&120_u64, &0), Inverse::Exactly(12));
+    assert_eq!(concat.unapply(&12_u64, &0), Inverse::Never);
+
+    let solver = Solver::<Custom>::new("9 4 3 2")?;
+    assert_eq!(
+        solver.solve(0b1010).map(|e| e.to_string()),
+        Some("9 - 4 - 3 ||2 2 = 10".to_string())
+    );
+    // and 9 - 4 % 3 ||2 2
+    assert_eq!(
+        solver.count(0b1010),
+        Perms::<Custom>::new("9 4 3 2")?
+            .filter(|actual| *actual == Some(0b1010))
+            .count() as u64
+    );
+
+    // a base of 1 never shifts, and there are no digits in base 0
+    struct Unary;
+    impl OpsSet for Unary {
+        const OPS: &'static [Operator] = &[Operator::Add, Operator::Concat(1)];
+    }
+    struct Nullary;
+    impl OpsSet for Nullary {
+        const OPS: &'static [Operator] = &[Operator::Concat(0)];
+    }
+    assert_eq!(
+        handle_puzzle::<Unary, u64>("3: 1 1")
+            .unwrap_err()
+            .to_string(),
+        "`||1` needs a base of at least 2"
+    );
+    assert!(Solver::<Nullary>::new("1 2").is_err());
+    assert!(Perms::<Unary>::new("1 2").is_err());
 
     Ok(())
 }
@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::RangeInclusive,
+};
 
 use itertools::Itertools;
 
@@ -10,90 +13,264 @@ pub fn puzzle2() -> Result<u32, Box<dyn std::error::Error>> {
     handle_puzzle2(std::fs::read_to_string("./inputs/dec08.txt")?.as_str())
 }
 
-pub fn within_boundary((x, y): (i32, i32), width: i32, height: i32) -> bool {
-    x >= 0 && x < width && y >= 0 && y < height
+/// Prints the map with the antinodes of `puzzle1` or `puzzle2` marked, how
+/// many each frequency has, and where different frequencies share one
+pub fn report(puzzle: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let harmonics = match puzzle {
+        "puzzle1" => Harmonics::Multiples(1..=1),
+        "puzzle2" => Harmonics::All,
+        _ => return Err(format!("unknown puzzle `{puzzle}`").into()),
+    };
+    let map = Map::parse(&std::fs::read_to_string("./inputs/dec08.txt")?);
+    let report = map.report(&harmonics);
+
+    print!("{}", map.overlay(&report));
+    println!("{} antinodes", report.len());
+    for (frequency, antinodes) in &report.by_frequency {
+        println!("`{}`: {}", *frequency as char, antinodes.len());
+    }
+    for (antinode, frequencies) in report.overlaps() {
+        let frequencies = frequencies.iter().map(|f| *f as char).join(", ");
+        println!("{antinode:?} is shared by {frequencies}");
+    }
+
+    Ok(())
 }
 
-fn handle_puzzle1(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
-    let (antennae, width, height) = load_input(input);
+type Point = (i32, i32);
 
-    let mut antinodes = HashSet::<(i32, i32)>::new();
-    for (_, antennae) in antennae {
-        for pair in antennae.iter().combinations(2) {
-            let [&(x1, y1), &(x2, y2)] = pair[0..2] else {
-                panic!()
-            };
+/// Which points on the line through a pair of antennas `a`, `b` are antinodes
+#[derive(Clone)]
+pub(crate) enum Harmonics {
+    /// `b + k(b - a)` and `a - k(b - a)` for each `k` in the range: 0 is the
+    /// antennas themselves, and 1 is where one antenna is twice as far away
+    /// as the other, as in part 1
+    Multiples(RangeInclusive<u32>),
+    /// every grid point exactly in line, between the antennas too, as in part 2
+    All,
+}
 
-            let (mx, my) = (x2 - x1, y2 - y1);
-            let p1 = (x2 + mx, y2 + my);
-            let p2 = (x1 - mx, y1 - my);
+/// Antennas by frequency, on a map that needn't be rectangular: rows can be
+/// ragged, a space is a hole in the map, and `mask` can punch more holes
+pub(crate) struct Map {
+    antennae: HashMap<u8, Vec<Point>>,
+    /// whether each cell can hold an antinode, row by row
+    allowed: Vec<Vec<bool>>,
+    /// the map as parsed, for `overlay`
+    rows: Vec<Vec<u8>>,
+    width: i32,
+}
 
-            if within_boundary(p1, width, height) {
-                antinodes.insert(p1);
-            }
-            if within_boundary(p2, width, height) {
-                antinodes.insert(p2);
+impl Map {
+    pub(crate) fn parse(input: &str) -> Self {
+        let mut antennae = HashMap::<u8, Vec<Point>>::new();
+        let mut allowed = vec![];
+        for (y, line) in input.lines().enumerate() {
+            let mut row = vec![];
+            for (x, c) in line.bytes().enumerate() {
+                match c {
+                    b'.' => {}
+                    b' ' => {}
+                    fq => antennae.entry(fq).or_default().push((x as i32, y as i32)),
+                }
+                row.push(c != b' ');
             }
+            allowed.push(row);
         }
-    }
+        let width = allowed.iter().map(|row| row.len()).max().unwrap_or(0) as i32;
 
-    Ok(antinodes.len() as u32)
-}
+        let rows = input.lines().map(|line| line.bytes().collect()).collect();
 
-fn load_input(input: &str) -> (HashMap<u8, HashSet<(i32, i32)>>, i32, i32) {
-    let mut antennae = HashMap::<u8, HashSet<(i32, i32)>>::new();
-    let width = input.lines().count() as i32;
-    let height = input.lines().next().unwrap().len() as i32;
-    let every_antenna = input.lines().enumerate().flat_map(|(y, line)| {
-        line.as_bytes()
-            .iter()
-            .enumerate()
-            .filter(|(_, c)| **c != b'.')
-            .map(move |(x, char)| (*char, y, x))
-    });
-    for (fq, y, x) in every_antenna {
-        antennae.entry(fq).or_default().insert((x as i32, y as i32));
+        Self {
+            antennae,
+            allowed,
+            rows,
+            width,
+        }
     }
-    (antennae, width, height)
-}
 
-fn handle_puzzle2(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
-    let (antennae, width, height) = load_input(input);
-
-    let mut antinodes = HashSet::<(i32, i32)>::new();
-    for (_, antennae) in antennae {
-        if antennae.len() > 2 {
-            antennae.iter().for_each(|point| {
-                antinodes.insert(*point);
-            });
+    /// no antinodes at `(x, y)`
+    #[cfg(test)]
+    pub(crate) fn mask(&mut self, (x, y): Point) {
+        if self.contains((x, y)) {
+            self.allowed[y as usize][x as usize] = false;
         }
-        for pair in antennae.iter().combinations(2) {
-            let [&(x1, y1), &(x2, y2)] = pair[0..2] else {
-                panic!()
-            };
-            let (mx, my) = (x2 - x1, y2 - y1);
-            let mut p = (x2, y2);
-            loop {
-                p = (p.0 + mx, p.1 + my);
-
-                if !within_boundary(p, width, height) {
-                    break;
+    }
+
+    pub(crate) fn contains(&self, (x, y): Point) -> bool {
+        x >= 0
+            && y >= 0
+            && self
+                .allowed
+                .get(y as usize)
+                .and_then(|row| row.get(x as usize))
+                .is_some_and(|allowed| *allowed)
+    }
+
+    /// Whether `(x, y)` is in the rectangle around the map. A line that's left
+    /// it can't come back, though one that's only crossed a hole can.
+    fn in_bounds(&self, (x, y): Point) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.allowed.len() as i32
+    }
+
+    /// Every antinode of the pair `a`, `b`, on the map
+    pub(crate) fn pair_antinodes(&self, a: Point, b: Point, harmonics: &Harmonics) -> Vec<Point> {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let mut antinodes = vec![];
+
+        match harmonics {
+            Harmonics::Multiples(range) => {
+                for k in range.clone() {
+                    // a multiple past `i32` is off any map, and so is one that
+                    // saturates on the way there
+                    let Ok(k) = i32::try_from(k) else {
+                        break;
+                    };
+                    let (kx, ky) = (k.saturating_mul(dx), k.saturating_mul(dy));
+                    let beyond_b = (b.0.saturating_add(kx), b.1.saturating_add(ky));
+                    let beyond_a = (a.0.saturating_sub(kx), a.1.saturating_sub(ky));
+                    if !self.in_bounds(beyond_b) && !self.in_bounds(beyond_a) {
+                        break;
+                    }
+                    antinodes.extend(
+                        [beyond_b, beyond_a]
+                            .into_iter()
+                            .filter(|p| self.contains(*p)),
+                    );
+                }
+            }
+            Harmonics::All => {
+                // the smallest step that stays on grid points
+                let g = gcd(dx.abs(), dy.abs()).max(1);
+                let (sx, sy) = (dx / g, dy / g);
+                for (sx, sy) in [(sx, sy), (-sx, -sy)] {
+                    let mut p = a;
+                    while self.in_bounds(p) {
+                        if self.contains(p) {
+                            antinodes.push(p);
+                        }
+                        p = (p.0 + sx, p.1 + sy);
+                    }
                 }
-                antinodes.insert(p);
             }
-            p = (x1, y1);
-            loop {
-                p = (p.0 - mx, p.1 - my);
+        }
+
+        antinodes
+    }
 
-                if !within_boundary(p, width, height) {
-                    break;
+    pub(crate) fn antinodes(&self, harmonics: &Harmonics) -> HashSet<Point> {
+        let mut antinodes = HashSet::new();
+        for antennae in self.antennae.values() {
+            for pair in antennae.iter().combinations(2) {
+                antinodes.extend(self.pair_antinodes(*pair[0], *pair[1], harmonics));
+            }
+        }
+
+        antinodes
+    }
+
+    /// Every antinode, grouped by frequency and traced back to the pairs of
+    /// antennas that made it
+    pub(crate) fn report(&self, harmonics: &Harmonics) -> Report {
+        let mut report = Report::default();
+        for (frequency, antennae) in &self.antennae {
+            for pair in antennae.iter().combinations(2) {
+                let pair = (*pair[0], *pair[1]);
+                for antinode in self.pair_antinodes(pair.0, pair.1, harmonics) {
+                    report
+                        .by_frequency
+                        .entry(*frequency)
+                        .or_default()
+                        .insert(antinode);
+                    report.sources.entry(antinode).or_default().push(Source {
+                        frequency: *frequency,
+                        pair,
+                    });
                 }
-                antinodes.insert(p);
             }
         }
+
+        for sources in report.sources.values_mut() {
+            sources.sort();
+        }
+
+        report
     }
 
-    Ok(antinodes.len() as u32)
+    /// The map as parsed, with a `#` on every empty cell that's an antinode.
+    /// Antennas and holes are left as they are.
+    pub(crate) fn overlay(&self, report: &Report) -> String {
+        let mut out = String::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                let antinode = report.sources.contains_key(&(x as i32, y as i32));
+                out.push(if antinode && *c == b'.' {
+                    '#'
+                } else {
+                    *c as char
+                });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+/// A pair of antennas of one frequency that put an antinode somewhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Source {
+    pub(crate) frequency: u8,
+    pub(crate) pair: (Point, Point),
+}
+
+#[derive(Default)]
+pub(crate) struct Report {
+    pub(crate) by_frequency: BTreeMap<u8, BTreeSet<Point>>,
+    /// every antinode, with each pair that put it there
+    pub(crate) sources: BTreeMap<Point, Vec<Source>>,
+}
+
+impl Report {
+    /// how many distinct antinodes there are, whatever their frequency
+    pub(crate) fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Antinodes that more than one frequency put in the same place, with
+    /// those frequencies
+    pub(crate) fn overlaps(&self) -> BTreeMap<Point, Vec<u8>> {
+        self.sources
+            .iter()
+            .filter_map(|(antinode, sources)| {
+                let frequencies = sources
+                    .iter()
+                    .map(|s| s.frequency)
+                    .dedup()
+                    .collect::<Vec<_>>();
+                (frequencies.len() > 1).then_some((*antinode, frequencies))
+            })
+            .collect()
+    }
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+fn handle_puzzle1(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let map = Map::parse(input);
+
+    Ok(map.antinodes(&Harmonics::Multiples(1..=1)).len() as u32)
+}
+
+fn handle_puzzle2(input: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let map = Map::parse(input);
+
+    Ok(map.antinodes(&Harmonics::All).len() as u32)
 }
 
 #[test]
@@ -148,3 +325,143 @@ fn test_puzzle2() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_antinodes_match_collinearity() -> Result<(), Box<dyn std::error::Error>> {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // a step of (2, 4) passes through (1, 2) on the way, and two antennas
+    // of a frequency are each in line with the pair
+    let map = Map::parse("a....\n.....\n..a..\n.....\n.....");
+    assert_eq!(
+        map.antinodes(&Harmonics::All),
+        HashSet::from([(0, 0), (1, 1), (2, 2), (3, 3), (4, 4)])
+    );
+    // multiples that don't fit an `i32` are nowhere near the map
+    assert!(map
+        .antinodes(&Harmonics::Multiples(1 << 31..=u32::MAX))
+        .is_empty());
+    assert!(map
+        .antinodes(&Harmonics::Multiples(1 << 30..=1 << 30))
+        .is_empty());
+
+    let mut rng = StdRng::seed_from_u64(0x2024_1208);
+    let mut random = move |bound: u64| rng.gen_range(0..bound);
+
+    for _ in 0..200 {
+        // ragged rows, holes, and a few antennas of a few frequencies
+        let input = (0..1 + random(12))
+            .map(|_| {
+                (0..random(14))
+                    .map(|_| match random(20) {
+                        0 => ' ',
+                        1 => 'a',
+                        2 => 'b',
+                        3 => 'c',
+                        _ => '.',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut map = Map::parse(&input);
+        map.mask((random(14) as i32, random(12) as i32));
+
+        let harmonics = [
+            Harmonics::All,
+            Harmonics::Multiples(1..=1),
+            Harmonics::Multiples(0..=2),
+            Harmonics::Multiples(2..=u32::MAX),
+        ];
+
+        for harmonics in harmonics {
+            // every point on the map, against every pair
+            let mut expected = HashSet::new();
+            for y in 0..12 {
+                for x in 0..14 {
+                    let p = (x, y);
+                    if !map.contains(p) {
+                        continue;
+                    }
+
+                    for antennae in map.antennae.values() {
+                        for pair in antennae.iter().combinations(2) {
+                            let (a, b) = (*pair[0], *pair[1]);
+                            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+                            let in_line = (p.0 - a.0) * dy == (p.1 - a.1) * dx;
+                            let hit = match &harmonics {
+                                Harmonics::All => in_line,
+                                Harmonics::Multiples(range) => (0..=14).any(|k| {
+                                    range.contains(&(k as u32))
+                                        && (p == (b.0 + k * dx, b.1 + k * dy)
+                                            || p == (a.0 - k * dx, a.1 - k * dy))
+                                }),
+                            };
+                            if hit {
+                                expected.insert(p);
+                            }
+                        }
+                    }
+                }
+            }
+
+            assert_eq!(map.antinodes(&harmonics), expected, "{input}");
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_report() -> Result<(), Box<dyn std::error::Error>> {
+    let input = r#"............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............"#;
+
+    let map = Map::parse(input);
+    let report = map.report(&Harmonics::Multiples(1..=1));
+
+    // the puzzle's picture, where one antinode is hidden under an `A`
+    assert_eq!(
+        map.overlay(&report),
+        r#"......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#.
+"#
+    );
+    assert_eq!(report.len(), 14);
+    assert_eq!(
+        report.sources[&(6, 5)],
+        vec![Source {
+            frequency: b'0',
+            pair: ((8, 1), (7, 3))
+        }]
+    );
+    assert_eq!(report.by_frequency[&b'A'].len(), 5);
+
+    // only (3, 1) is an antinode of both frequencies
+    assert_eq!(
+        report.overlaps(),
+        BTreeMap::from([((3, 1), vec![b'0', b'A'])])
+    );
+
+    Ok(())
+}
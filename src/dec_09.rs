@@ -1,214 +1,118 @@
-use std::collections::{BinaryHeap, HashMap, LinkedList};
+mod defrag;
+mod free_spans;
+mod image;
 
-use core::cmp::Reverse;
+use std::path::Path;
+
+use defrag::Defrag;
+use image::{DiskImage, Encoding, Fit, Strategy};
+
+static INPUT: &str = "./inputs/dec09.txt";
 
 pub fn puzzle1() -> Result<usize, Box<dyn std::error::Error>> {
-    handle_puzzle1(std::fs::read_to_string("./inputs/dec09.txt")?.as_str())
+    handle_puzzle1(std::fs::read_to_string(INPUT)?.as_str())
 }
 
 pub fn puzzle2() -> Result<usize, Box<dyn std::error::Error>> {
-    handle_puzzle2(std::fs::read_to_string("./inputs/dec09.txt")?.as_str())
+    handle_puzzle2(std::fs::read_to_string(INPUT)?.as_str())
 }
 
-fn handle_puzzle1(input: &str) -> Result<usize, Box<dyn std::error::Error>> {
-    // Approach: double-pointer through a LinkedList
-
-    // generate files && free blocks in one list
-    let mut blocks = input
-        .chars()
-        .enumerate()
-        .map(|(i, c)| {
-            let size = c.to_digit(10).unwrap() as usize;
-            if i % 2 == 0 {
-                Block::File(size, i / 2)
-            } else {
-                Block::Free(size)
-            }
-        })
-        .collect::<LinkedList<_>>();
-
-    // an accumulator for ultracompact disk blocks. blocks_right does not exist because it has no
-    // bearing on our computation for checksum
-    let mut blocks_left: LinkedList<Block> = LinkedList::new();
-
-    // double pointer is achieved by popping off of either side of the linked list.
-    while !blocks.is_empty() {
-        // get the next free block from the left ptr, stashing compact file blocks
-        let free = loop {
-            if let Some(block) = blocks.pop_front() {
-                match block {
-                    Block::Free(_) => break Some(block),
-                    Block::File(_, _) => {
-                        blocks_left.push_back(block);
-                        continue;
-                    }
-                }
-            } else {
-                break None;
-            }
-        };
-        if blocks.is_empty() {
-            break;
-        }
-        // discard unimportant free blocks, until we find a file block
-        let file = loop {
-            if let Some(block) = blocks.pop_back() {
-                match block {
-                    Block::Free(_) => {
-                        continue;
-                    }
-                    Block::File(_, _) => break Some(block),
-                }
-            } else {
-                break None;
-            }
-        };
-
-        // compute the transfer from right to left, discarding anything possible
-        if let (Some(mut free), Some(mut file)) = (free, file) {
-            let frag = free.frag(&mut file).unwrap();
-            blocks_left.push_back(frag);
-            if free.size() > 0 {
-                blocks.push_front(free);
-            }
-            if file.size() > 0 {
-                blocks.push_back(file);
-            }
-        }
-    }
+/// Both puzzles for the disk map at `path`, in the encoding its extension
+/// names (see `Encoding::for_path`)
+pub fn run_file(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let image = load(path)?;
+
+    println!("dec_09 ({path})");
+    println!(
+        "\tPuzzle #1 -- RESULT: {}",
+        checksum_after(image.clone(), Strategy::Fragment)
+    );
+    println!(
+        "\tPuzzle #2 -- RESULT: {}",
+        checksum_after(image, Strategy::WholeFile(Fit::First))
+    );
 
-    Ok(Block::get_checksum(blocks_left.iter()))
+    Ok(())
 }
 
-fn handle_puzzle2(input: &str) -> Result<usize, Box<dyn std::error::Error>> {
-    // Approach: exploit problem constraints to create a constant-sized map of min-heaps, leading to
-    // acceptable/optimal(?) leftmost position search
-
-    let mut holes = HashMap::<usize, BinaryHeap<Reverse<usize>>>::new();
-    for i in 0..=9 {
-        holes.insert(i, BinaryHeap::new());
+/// Compacts the disk every way there is, printing how each one leaves it
+pub fn compare_strategies() -> Result<(), Box<dyn std::error::Error>> {
+    let image = load(INPUT)?;
+    let strategies = [
+        Strategy::Fragment,
+        Strategy::WholeFile(Fit::First),
+        Strategy::WholeFile(Fit::Best),
+    ];
+
+    for strategy in strategies {
+        let mut compacted = image.clone();
+        let moves = compacted.compact(strategy);
+        println!("{strategy:?}: {moves} moves, {:?}", compacted.stats());
     }
 
-    let mut files_in = Vec::<(usize, usize, usize)>::new();
-    let mut files_out = Vec::<(usize, usize, usize)>::new();
-    let mut entity_pos = 0;
-
-    let disk_map = input
-        .chars()
-        .map(|c| c.to_digit(10).unwrap() as usize)
-        .enumerate();
-
-    // init stuff
-    for (i, entity_size) in disk_map {
-        if i % 2 == 0 {
-            let file_id = i / 2;
-            files_in.push((entity_pos, entity_size, file_id))
-        } else {
-            holes
-                .get_mut(&entity_size)
-                .unwrap()
-                .push(Reverse(entity_pos));
-        }
-
-        entity_pos += entity_size;
-    }
-
-    // poke Map (sizes) -> MinHeap(positions) to find leftmost position that fits the next file
-    for (mut file_pos, file_size, file_id) in files_in.into_iter().rev() {
-        let (mut best_pos, mut its_size) = (None, usize::MAX);
-
-        const MAX_ENTITY_SIZE: usize = 9;
-        for free_size in file_size..=MAX_ENTITY_SIZE {
-            let min_heap = holes.get_mut(&free_size).unwrap();
-            let candidate_pos: usize = match min_heap.peek() {
-                Some(Reverse(pos)) => *pos,
-                None => usize::MAX,
-            };
-
-            if candidate_pos < file_pos && (best_pos.is_none() || candidate_pos < best_pos.unwrap())
-            {
-                best_pos = Some(candidate_pos);
-                its_size = free_size;
-            }
-        }
+    Ok(())
+}
 
-        if let Some(best_pos) = best_pos {
-            holes.get_mut(&its_size).unwrap().pop();
-            file_pos = best_pos;
-            // add new, smaller hole back to the pile if necessary
-            if file_size < its_size {
-                let new_free_size = its_size - file_size;
-                let new_free_pos = file_pos + file_size;
-                holes
-                    .get_mut(&new_free_size)
-                    .unwrap()
-                    .push(Reverse(new_free_pos));
+/// Steps through part 2's compaction of the disk map at `path`, reading
+/// stdin: a number makes that many moves, `u` takes the last one back, and a
+/// blank line or the end of input makes the rest. Prints each move and the
+/// disk after each line, then checks the log replays to the same disk.
+pub fn step_through(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let image = load(path)?;
+    let mut compacted = image.clone();
+    let mut defrag = Defrag::new(&mut compacted, Strategy::WholeFile(Fit::First));
+
+    println!("{}", defrag.image());
+    for line in std::io::stdin().lines() {
+        match line?.trim() {
+            "" => break,
+            "u" => match defrag.undo() {
+                Some(m) => println!("undid {m}"),
+                None => println!("nothing to undo"),
+            },
+            n => {
+                let made = defrag.run(n.parse()?);
+                for m in &defrag.log()[defrag.log().len() - made..] {
+                    println!("{m}");
+                }
             }
         }
-
-        files_out.push((file_pos, file_size, file_id));
+        println!("{}", defrag.image());
     }
+    defrag.finish();
+    let log = defrag.log().to_vec();
 
-    let mut sum = 0;
-
-    for (pos, size, id) in files_out.into_iter().rev() {
-        sum += id * (pos..pos + size).sum::<usize>();
+    let mut replayed = image;
+    replayed.replay(&log)?;
+    if replayed.checksum() != compacted.checksum() {
+        return Err("replaying the log led somewhere else".into());
     }
+    println!("{} moves, checksum {}", log.len(), compacted.checksum());
 
-    Ok(sum)
-}
-
-enum Block {
-    // size
-    Free(usize),
-    // size, id
-    File(usize, usize),
+    Ok(())
 }
 
-impl Block {
-    pub fn size(&self) -> usize {
-        match self {
-            Block::Free(size) => *size,
-            Block::File(size, _) => *size,
-        }
-    }
+fn load(path: impl AsRef<Path>) -> Result<DiskImage, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let image = DiskImage::decode(&std::fs::read(path)?, Encoding::for_path(path))?;
 
-    /// fragment a Free block with File block `other`.
-    /// Modifies this block in place, and produces a fragmented File block as output
-    pub fn frag(&mut self, other: &mut Block) -> Option<Block> {
-        if let Block::Free(free_size) = self {
-            if let Block::File(file_size, file_id) = other {
-                let transfer_size = (*free_size).min(*file_size);
-                *free_size -= transfer_size;
-                *file_size -= transfer_size;
-
-                return Some(Block::File(transfer_size, *file_id));
-            }
-        }
+    Ok(image)
+}
 
-        None
-    }
+fn checksum_after(mut image: DiskImage, strategy: Strategy) -> usize {
+    image.compact(strategy);
+    image.checksum()
+}
 
-    /// compute the checksum
-    fn get_checksum<'a, I>(iter: I) -> usize
-    where
-        I: Iterator<Item = &'a Block>,
-    {
-        let mut i = 0;
-        let mut chksm = 0;
-        for block in iter {
-            match block {
-                Block::Free(size) => i += size,
-                Block::File(size, id) => {
-                    chksm += *id * (i..i + size).sum::<usize>();
-
-                    i += size;
-                }
-            }
-        }
+fn handle_puzzle1(input: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    Ok(checksum_after(DiskImage::parse(input)?, Strategy::Fragment))
+}
 
-        chksm
-    }
+fn handle_puzzle2(input: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    Ok(checksum_after(
+        DiskImage::parse(input)?,
+        Strategy::WholeFile(Fit::First),
+    ))
 }
 
 #[test]
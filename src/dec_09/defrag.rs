@@ -0,0 +1,183 @@
+use super::free_spans::FreeSpans;
+use super::image::{DiskImage, Fit, Move, Strategy};
+
+/// Compacts a disk a move at a time, logging each move so it can be undone,
+/// or replayed on another copy of the disk
+pub(crate) struct Defrag<'d> {
+    image: &'d mut DiskImage,
+    strategy: Strategy,
+    /// for whole-file compaction, each file that was in one piece at the
+    /// start, as `(file, from, len)` in the order they're tried
+    files: Vec<(usize, usize, usize)>,
+    /// how far it's got: for `Fragment` there's nothing free before this,
+    /// and for `WholeFile` it's the next of `files` to try
+    progress: usize,
+    /// for first-fit, built when it's first needed and again after an undo
+    spans: Option<FreeSpans>,
+    log: Vec<Move>,
+    /// `progress` before each move in `log`
+    marks: Vec<usize>,
+}
+
+impl<'d> Defrag<'d> {
+    pub(crate) fn new(image: &'d mut DiskImage, strategy: Strategy) -> Self {
+        let mut files = vec![];
+        if let Strategy::WholeFile(_) = strategy {
+            // moving one file doesn't move any other, so these stay put
+            // until it's their turn
+            let mut pieces = vec![vec![]; image.files()];
+            for (start, run) in image.runs() {
+                if let Some(file) = run.file {
+                    pieces[file].push((start, run.len));
+                }
+            }
+            for (file, pieces) in pieces.iter().enumerate().rev() {
+                if let [(from, len)] = pieces[..] {
+                    files.push((file, from, len));
+                }
+            }
+        }
+
+        Self {
+            image,
+            strategy,
+            files,
+            progress: 0,
+            spans: None,
+            log: vec![],
+            marks: vec![],
+        }
+    }
+
+    pub(crate) fn image(&self) -> &DiskImage {
+        self.image
+    }
+
+    /// every move so far, oldest first
+    pub(crate) fn log(&self) -> &[Move] {
+        &self.log
+    }
+
+    /// Makes the next move, or `None` once there are none left
+    pub(crate) fn step(&mut self) -> Option<Move> {
+        let mark = self.progress;
+        let m = match self.strategy {
+            Strategy::Fragment => {
+                let m = self.image.next_fragment(self.progress)?;
+                self.progress = m.to;
+                m
+            }
+            Strategy::WholeFile(fit) => loop {
+                let (file, from, len) = *self.files.get(self.progress)?;
+                self.progress += 1;
+
+                let to = match fit {
+                    Fit::First => self
+                        .spans
+                        .get_or_insert_with(|| FreeSpans::new(self.image))
+                        .first_fit(len, from),
+                    Fit::Best => self.image.best_fit(len, from),
+                };
+                if let Some(to) = to {
+                    break Move {
+                        file,
+                        from,
+                        to,
+                        len,
+                    };
+                }
+            },
+        };
+
+        self.image.apply(&m);
+        if let Some(spans) = &mut self.spans {
+            spans.moved(self.image, m.from, m.to);
+        }
+        self.log.push(m);
+        self.marks.push(mark);
+
+        Some(m)
+    }
+
+    /// Makes up to `n` moves, returning how many it made
+    pub(crate) fn run(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.step().is_some()).count()
+    }
+
+    /// Makes every move left, returning how many there were
+    pub(crate) fn finish(&mut self) -> usize {
+        self.run(usize::MAX)
+    }
+
+    /// Takes back the last move, so the next step makes it again
+    pub(crate) fn undo(&mut self) -> Option<Move> {
+        let m = self.log.pop()?;
+        self.progress = self.marks.pop()?;
+        self.image.apply(&m.reversed());
+        self.spans = None;
+
+        Some(m)
+    }
+}
+
+#[test]
+fn test_steps() -> Result<(), Box<dyn std::error::Error>> {
+    // the puzzle's pictures of part 2, a file at a time
+    let mut image = DiskImage::parse("2333133121414131402")?;
+    let original = image.clone();
+    let mut defrag = Defrag::new(&mut image, Strategy::WholeFile(Fit::First));
+    let mut frames = vec![];
+    while defrag.step().is_some() {
+        frames.push(defrag.image().to_string());
+    }
+    assert_eq!(
+        frames,
+        [
+            "0099.111...2...333.44.5555.6666.777.8888..",
+            "0099.1117772...333.44.5555.6666.....8888..",
+            "0099.111777244.333....5555.6666.....8888..",
+            "00992111777.44.333....5555.6666.....8888..",
+        ]
+    );
+    let m = |(file, from, to, len)| Move {
+        file,
+        from,
+        to,
+        len,
+    };
+    assert_eq!(defrag.log()[0], m((9, 40, 2, 2)));
+
+    // taking moves back, then making them again
+    assert_eq!(defrag.undo(), Some(m((2, 11, 4, 1))));
+    assert_eq!(defrag.undo(), Some(m((4, 19, 12, 2))));
+    assert_eq!(defrag.image().to_string(), frames[1]);
+    assert_eq!(defrag.run(1), 1);
+    assert_eq!(defrag.image().to_string(), frames[2]);
+    assert_eq!(defrag.finish(), 1);
+    assert_eq!(defrag.image().to_string(), frames[3]);
+
+    let log = defrag.log().to_vec();
+    let mut replayed = original.clone();
+    replayed.replay(&log)?;
+    assert_eq!(replayed.to_string(), frames[3]);
+
+    // the log only makes sense on the disk it came from
+    let mut fragmented = original.clone();
+    fragmented.compact(Strategy::Fragment);
+    assert_eq!(
+        fragmented.replay(&log).err(),
+        Some("move 0, 2 of file 9 from 40 to 2, doesn't fit the disk".to_string())
+    );
+
+    // fragmenting moves as many blocks as fit at once
+    let mut image = DiskImage::parse("12345")?;
+    let mut defrag = Defrag::new(&mut image, Strategy::Fragment);
+    assert_eq!(defrag.run(1), 1);
+    assert_eq!(defrag.image().to_string(), "022111....222..");
+    assert_eq!(defrag.finish(), 1);
+    assert_eq!(defrag.image().to_string(), "022111222......");
+    assert_eq!(defrag.undo(), Some(m((2, 10, 6, 3))));
+    assert_eq!(defrag.image().to_string(), "022111....222..");
+
+    Ok(())
+}
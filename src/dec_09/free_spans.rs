@@ -0,0 +1,98 @@
+use super::image::DiskImage;
+
+/// A max segment tree over a disk's free runs, for finding the leftmost one a
+/// file fits in, in `O(log n)` however long the runs are.
+///
+/// Each leaf is a slot, from the start of one run on the disk it was built
+/// from up to the next, and holds the free run that starts in that slot now,
+/// if any. Whole-file moves only ever fill a free run from its start, or free
+/// a file's run entirely, so a slot never has two free runs starting in it.
+pub(crate) struct FreeSpans {
+    slots: Vec<usize>,
+    /// where each slot's free run starts, if it has one
+    starts: Vec<usize>,
+    /// the longest free run under each node, leaves from `leaves` on
+    longest: Vec<usize>,
+    leaves: usize,
+}
+
+impl FreeSpans {
+    pub(crate) fn new(image: &DiskImage) -> Self {
+        let slots = image.runs().map(|(start, _)| start).collect::<Vec<_>>();
+        let leaves = slots.len().next_power_of_two();
+        let mut spans = Self {
+            starts: slots.clone(),
+            slots,
+            longest: vec![0; 2 * leaves],
+            leaves,
+        };
+
+        for (i, (start, run)) in image.runs().enumerate() {
+            if run.file.is_none() {
+                spans.set(i, start, run.len);
+            }
+        }
+
+        spans
+    }
+
+    fn set(&mut self, slot: usize, start: usize, len: usize) {
+        self.starts[slot] = start;
+        let mut node = self.leaves + slot;
+        self.longest[node] = len;
+        while node > 1 {
+            node /= 2;
+            self.longest[node] = self.longest[2 * node].max(self.longest[2 * node + 1]);
+        }
+    }
+
+    /// the slot `at` falls in
+    fn slot(&self, at: usize) -> usize {
+        self.slots.partition_point(|start| *start <= at) - 1
+    }
+
+    /// The start of the leftmost free run of at least `len` blocks, if it's
+    /// before `before`
+    pub(crate) fn first_fit(&self, len: usize, before: usize) -> Option<usize> {
+        if self.longest[1] < len {
+            return None;
+        }
+
+        let mut node = 1;
+        while node < self.leaves {
+            node = if self.longest[2 * node] >= len {
+                2 * node
+            } else {
+                2 * node + 1
+            };
+        }
+        let start = self.starts[node - self.leaves];
+
+        (start < before).then_some(start)
+    }
+
+    /// Catches up with `image` after it's moved a whole run of a file from
+    /// `from` to the start of the free run at `to`
+    pub(crate) fn moved(&mut self, image: &DiskImage, from: usize, to: usize) {
+        // the run `to` was in shrinks, and the one freed at `from` may have
+        // merged into the runs either side
+        let freed = image.free_run_at(from).map_or(from, |(start, _)| start);
+        let slots = [
+            self.slot(to),
+            self.slot(freed),
+            self.slot(from),
+            self.slot(from) + 1,
+        ];
+
+        for slot in slots {
+            if slot >= self.slots.len() {
+                continue;
+            }
+            let end = self.slots.get(slot + 1).copied().unwrap_or(usize::MAX);
+            let (start, len) = image
+                .free_run_starting_in(self.slots[slot]..end)
+                .unwrap_or((self.slots[slot], 0));
+            self.set(slot, start, len);
+        }
+    }
+}
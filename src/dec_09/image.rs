@@ -0,0 +1,637 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::path::Path;
+
+use super::defrag::Defrag;
+
+/// A stretch of blocks that all belong to one file, or are all free
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Run {
+    pub(crate) len: usize,
+    pub(crate) file: Option<usize>,
+}
+
+/// `len` blocks of `file` going from `from` to `to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Move {
+    pub(crate) file: usize,
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    pub(crate) len: usize,
+}
+
+impl Move {
+    /// the move that puts this one back
+    pub(crate) fn reversed(&self) -> Self {
+        Self {
+            from: self.to,
+            to: self.from,
+            ..*self
+        }
+    }
+}
+
+impl std::fmt::Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (file, len) = (self.file, self.len);
+        write!(f, "{len} of file {file} from {} to {}", self.from, self.to)
+    }
+}
+
+/// Where whole-file compaction puts each file, out of the free runs left of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fit {
+    /// the leftmost run it fits in, as in part 2
+    First,
+    /// the shortest run it fits in, the leftmost of those on a tie
+    Best,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Strategy {
+    /// fill the leftmost free block from the rightmost file block, splitting
+    /// files up as needed, as in part 1
+    Fragment,
+    /// try each file once, from the highest id down, moving it left in one
+    /// piece if there's room. Files already in pieces stay where they are.
+    WholeFile(Fit),
+}
+
+/// How a disk map's lengths are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    /// a digit per length, so none are longer than 9, as in the puzzle
+    Dense,
+    /// decimal lengths separated by commas
+    Commas,
+    /// unsigned LEB128: 7 bits per byte, low bits first, with the top bit set
+    /// on every byte but a length's last
+    Varint,
+}
+
+impl Encoding {
+    /// By file extension: `.csv` for `Commas`, `.bin` for `Varint`, and
+    /// `Dense` for anything else, including the puzzle's own `.txt`
+    pub(crate) fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Encoding::Commas,
+            Some("bin") => Encoding::Varint,
+            _ => Encoding::Dense,
+        }
+    }
+}
+
+/// How a disk is laid out, e.g. after compacting it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Stats {
+    pub(crate) checksum: usize,
+    /// file runs, so a file in one piece counts once
+    pub(crate) extents: usize,
+    /// files in more than one piece
+    pub(crate) fragmented: usize,
+    /// free runs between file blocks, i.e. before the last one
+    pub(crate) holes: usize,
+    /// free blocks in those holes
+    pub(crate) wasted: usize,
+    pub(crate) largest_hole: usize,
+}
+
+/// The disk block by block, stored as runs
+#[derive(Clone)]
+pub(crate) struct DiskImage {
+    /// run starts to runs, covering the disk end to end. Neighbouring runs
+    /// always differ, so a file in one piece is a single run.
+    runs: BTreeMap<usize, Run>,
+    /// the starts of the free runs, by size class
+    free: BTreeMap<usize, BTreeSet<usize>>,
+    files: usize,
+}
+
+impl DiskImage {
+    /// From the puzzle's dense disk map
+    pub(crate) fn parse(input: &str) -> Result<Self, String> {
+        Self::decode(input.as_bytes(), Encoding::Dense)
+    }
+
+    /// From a disk map: alternating file and free lengths, with the files'
+    /// ids counting up from 0
+    pub(crate) fn decode(input: &[u8], encoding: Encoding) -> Result<Self, String> {
+        let lens = match encoding {
+            Encoding::Dense => input
+                .trim_ascii()
+                .iter()
+                .map(|c| match c {
+                    b'0'..=b'9' => Ok((c - b'0') as usize),
+                    _ => Err(format!("unexpected `{}` in the disk map", *c as char)),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            Encoding::Commas => String::from_utf8_lossy(input)
+                .trim()
+                .split_terminator(',')
+                .map(str::trim)
+                .map(|len| len.parse().map_err(|_| format!("`{len}` isn't a length")))
+                .collect::<Result<Vec<_>, _>>()?,
+            Encoding::Varint => {
+                let mut lens = vec![];
+                let (mut len, mut shift) = (0_usize, 0);
+                for byte in input {
+                    let bits = (byte & 0x7f) as usize;
+                    if shift >= usize::BITS || (bits << shift) >> shift != bits {
+                        return Err(format!("length {} is too long", lens.len()));
+                    }
+                    len |= bits << shift;
+                    shift += 7;
+                    if byte & 0x80 == 0 {
+                        lens.push(len);
+                        (len, shift) = (0, 0);
+                    }
+                }
+                if shift > 0 {
+                    return Err("the disk map ends partway through a length".to_string());
+                }
+                lens
+            }
+        };
+
+        Ok(Self::from_lens(&lens))
+    }
+
+    fn from_lens(lens: &[usize]) -> Self {
+        let mut image = Self {
+            runs: BTreeMap::new(),
+            free: BTreeMap::new(),
+            files: lens.len().div_ceil(2),
+        };
+
+        let mut start = 0;
+        for (i, len) in lens.iter().enumerate() {
+            let file = (i % 2 == 0).then_some(i / 2);
+            image.set(start, *len, file);
+            start += len;
+        }
+
+        image
+    }
+
+    /// every run, with its start, left to right
+    pub(crate) fn runs(&self) -> impl Iterator<Item = (usize, Run)> + '_ {
+        self.runs.iter().map(|(start, run)| (*start, *run))
+    }
+
+    /// Hands `[start, start + len)` to `file`, or frees it for `None`
+    fn set(&mut self, start: usize, len: usize, file: Option<usize>) {
+        if len == 0 {
+            return;
+        }
+        let end = start + len;
+        self.split_at(start);
+        self.split_at(end);
+
+        let covered = self
+            .runs
+            .range(start..end)
+            .map(|(start, run)| (*start, *run))
+            .collect::<Vec<_>>();
+        for (start, run) in covered {
+            self.remove(start, run);
+        }
+        self.insert(start, Run { len, file });
+
+        // merge with whichever neighbours now match
+        if let Some((&next, &run)) = self.runs.range(end..).next() {
+            if next == end && run.file == file {
+                self.merge(start, next);
+            }
+        }
+        if let Some((&prev, &run)) = self.runs.range(..start).next_back() {
+            if prev + run.len == start && run.file == file {
+                self.merge(prev, start);
+            }
+        }
+    }
+
+    /// makes sure a run starts at `at`, unless it's off the end
+    fn split_at(&mut self, at: usize) {
+        let Some((&start, &run)) = self.runs.range(..at).next_back() else {
+            return;
+        };
+        if at < start + run.len {
+            self.remove(start, run);
+            let head = at - start;
+            self.insert(start, Run { len: head, ..run });
+            self.insert(
+                at,
+                Run {
+                    len: run.len - head,
+                    ..run
+                },
+            );
+        }
+    }
+
+    /// joins the run at `right` onto the one at `left`, right next to it
+    fn merge(&mut self, left: usize, right: usize) {
+        let (l, r) = (self.runs[&left], self.runs[&right]);
+        self.remove(left, l);
+        self.remove(right, r);
+        self.insert(
+            left,
+            Run {
+                len: l.len + r.len,
+                ..l
+            },
+        );
+    }
+
+    fn insert(&mut self, start: usize, run: Run) {
+        if run.file.is_none() {
+            self.free.entry(run.len).or_default().insert(start);
+        }
+        self.runs.insert(start, run);
+    }
+
+    fn remove(&mut self, start: usize, run: Run) {
+        if run.file.is_none() {
+            let starts = self.free.get_mut(&run.len).unwrap();
+            starts.remove(&start);
+            if starts.is_empty() {
+                self.free.remove(&run.len);
+            }
+        }
+        self.runs.remove(&start);
+    }
+
+    /// Whether `m` takes blocks that all belong to its file, to blocks that
+    /// are all free
+    pub(crate) fn can_apply(&self, m: &Move) -> bool {
+        self.owned_by(m.from, m.len, Some(m.file)) && self.owned_by(m.to, m.len, None)
+    }
+
+    fn owned_by(&self, start: usize, len: usize, file: Option<usize>) -> bool {
+        self.runs
+            .range(..=start)
+            .next_back()
+            .is_some_and(|(s, run)| run.file == file && start + len <= s + run.len)
+    }
+
+    pub(crate) fn apply(&mut self, m: &Move) {
+        debug_assert!(self.can_apply(m), "{m:?} doesn't fit the disk");
+        self.set(m.from, m.len, None);
+        self.set(m.to, m.len, Some(m.file));
+    }
+
+    /// Applies each move in `log` in turn, as long as each one fits
+    pub(crate) fn replay(&mut self, log: &[Move]) -> Result<(), String> {
+        for (i, m) in log.iter().enumerate() {
+            if !self.can_apply(m) {
+                return Err(format!("move {i}, {m}, doesn't fit the disk"));
+            }
+            self.apply(m);
+        }
+
+        Ok(())
+    }
+
+    /// Moves files left until `strategy` has nothing more to do, returning
+    /// how many moves it took
+    pub(crate) fn compact(&mut self, strategy: Strategy) -> usize {
+        Defrag::new(self, strategy).finish()
+    }
+
+    /// how many files there are, empty ones included
+    pub(crate) fn files(&self) -> usize {
+        self.files
+    }
+
+    /// the rightmost file blocks that fit in the first free run from `cursor`
+    pub(crate) fn next_fragment(&self, cursor: usize) -> Option<Move> {
+        let (to, hole) = self
+            .runs
+            .range(cursor..)
+            .find(|(_, run)| run.file.is_none())?;
+        let (from, run) = self.runs.iter().rev().find(|(_, run)| run.file.is_some())?;
+        if from < to {
+            return None;
+        }
+
+        let len = hole.len.min(run.len);
+        Some(Move {
+            file: run.file?,
+            from: from + run.len - len,
+            to: *to,
+            len,
+        })
+    }
+
+    /// The start of the shortest free run of at least `len` blocks before
+    /// `before`, the leftmost on a tie. Size classes with nothing early
+    /// enough are skipped one by one, so this is only quick while there are
+    /// few of them.
+    pub(crate) fn best_fit(&self, len: usize, before: usize) -> Option<usize> {
+        self.free
+            .range(len..)
+            .filter_map(|(_, starts)| starts.first().copied())
+            .find(|start| *start < before)
+    }
+
+    /// the free run `at` is in, as its start and length
+    pub(crate) fn free_run_at(&self, at: usize) -> Option<(usize, usize)> {
+        let (start, run) = self.runs.range(..=at).next_back()?;
+        (run.file.is_none() && at < start + run.len).then_some((*start, run.len))
+    }
+
+    /// the first free run that starts in `range`, as its start and length
+    pub(crate) fn free_run_starting_in(&self, range: Range<usize>) -> Option<(usize, usize)> {
+        self.runs
+            .range(range)
+            .find(|(_, run)| run.file.is_none())
+            .map(|(start, run)| (*start, run.len))
+    }
+
+    /// each file's id times each of its blocks' positions, summed
+    pub(crate) fn checksum(&self) -> usize {
+        self.runs()
+            .filter_map(|(start, run)| {
+                run.file
+                    .map(|id| id * (start..start + run.len).sum::<usize>())
+            })
+            .sum()
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        let mut pieces = vec![0; self.files];
+        let (mut holes, mut wasted, mut largest_hole) = (0, 0, 0);
+        let mut pending = vec![];
+        for (_, run) in self.runs() {
+            match run.file {
+                Some(file) => {
+                    pieces[file] += 1;
+                    // the free runs so far are between files after all
+                    for len in pending.drain(..) {
+                        holes += 1;
+                        wasted += len;
+                        largest_hole = largest_hole.max(len);
+                    }
+                }
+                None => pending.push(run.len),
+            }
+        }
+
+        Stats {
+            checksum: self.checksum(),
+            extents: pieces.iter().sum(),
+            fragmented: pieces.iter().filter(|n| **n > 1).count(),
+            holes,
+            wasted,
+            largest_hole,
+        }
+    }
+}
+
+/// The puzzle's picture: a block per character, each the last digit of its
+/// file's id, or `.` for free
+impl std::fmt::Display for DiskImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (_, run) in self.runs() {
+            let c = match run.file {
+                Some(id) => char::from(b'0' + (id % 10) as u8),
+                None => '.',
+            };
+            for _ in 0..run.len {
+                write!(f, "{c}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_compaction() -> Result<(), Box<dyn std::error::Error>> {
+    let image = DiskImage::parse("2333133121414131402")?;
+    assert_eq!(
+        image.to_string(),
+        "00...111...2...333.44.5555.6666.777.888899"
+    );
+
+    let mut fragmented = image.clone();
+    fragmented.compact(Strategy::Fragment);
+    assert_eq!(
+        fragmented.to_string(),
+        "0099811188827773336446555566.............."
+    );
+    assert_eq!(
+        fragmented.stats(),
+        Stats {
+            checksum: 1928,
+            extents: 13,
+            fragmented: 2,
+            holes: 0,
+            wasted: 0,
+            largest_hole: 0,
+        }
+    );
+
+    let mut first = image.clone();
+    first.compact(Strategy::WholeFile(Fit::First));
+    assert_eq!(
+        first.to_string(),
+        "00992111777.44.333....5555.6666.....8888.."
+    );
+    assert_eq!(first.checksum(), 2858);
+    assert_eq!(first.stats().holes, 5);
+
+    // on the example the two fits agree, but here 2 skips the first hole
+    // for the one it fills exactly, leaving the first for 1
+    let image = DiskImage::parse("13122")?;
+    let (mut first, mut best) = (image.clone(), image);
+    first.compact(Strategy::WholeFile(Fit::First));
+    best.compact(Strategy::WholeFile(Fit::Best));
+    assert_eq!(first.to_string(), "0221.....");
+    assert_eq!(best.to_string(), "01...22..");
+
+    assert_eq!(
+        DiskImage::parse("12x").err(),
+        Some("unexpected `x` in the disk map".to_string())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_moves() -> Result<(), Box<dyn std::error::Error>> {
+    let image = DiskImage::parse("21212")?;
+    let mut moved = image.clone();
+
+    let m = Move {
+        file: 2,
+        from: 7,
+        to: 2,
+        len: 1,
+    };
+    assert!(moved.can_apply(&m));
+    assert!(!moved.can_apply(&Move { to: 3, ..m }));
+    assert!(!moved.can_apply(&Move { file: 1, ..m }));
+    moved.apply(&m);
+    assert_eq!(moved.to_string(), "00211.2.");
+    assert_eq!((moved.stats().extents, moved.stats().fragmented), (4, 1));
+
+    // moving it back merges the runs again
+    moved.apply(&Move {
+        from: 2,
+        to: 7,
+        ..m
+    });
+    assert_eq!(
+        moved.runs().collect::<Vec<_>>(),
+        image.runs().collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_encodings() -> Result<(), Box<dyn std::error::Error>> {
+    let runs = |image: DiskImage| image.runs().collect::<Vec<_>>();
+    let dense = DiskImage::parse("2333133121414131402\n")?;
+    let commas = DiskImage::decode(b"2,3,3,3,1,3,3,1,2,1,4,1,4,1,3,1,4,0,2", Encoding::Commas)?;
+    assert_eq!(runs(commas), runs(dense.clone()));
+
+    let bytes = "2333133121414131402"
+        .bytes()
+        .map(|c| c - b'0')
+        .collect::<Vec<_>>();
+    assert_eq!(
+        runs(DiskImage::decode(&bytes, Encoding::Varint)?),
+        runs(dense)
+    );
+
+    assert_eq!(Encoding::for_path(Path::new("disk.csv")), Encoding::Commas);
+    assert_eq!(
+        Encoding::for_path(Path::new("./disk.bin")),
+        Encoding::Varint
+    );
+    assert_eq!(
+        Encoding::for_path(Path::new("inputs/dec09.txt")),
+        Encoding::Dense
+    );
+    assert_eq!(Encoding::for_path(Path::new("disk")), Encoding::Dense);
+
+    // 300 is 0b10_0101100, so two bytes, low bits first
+    let image = DiskImage::decode(&[0x02, 0xac, 0x02, 0x0c], Encoding::Varint)?;
+    assert_eq!(
+        runs(image),
+        vec![
+            (
+                0,
+                Run {
+                    len: 2,
+                    file: Some(0)
+                }
+            ),
+            (
+                2,
+                Run {
+                    len: 300,
+                    file: None
+                }
+            ),
+            (
+                302,
+                Run {
+                    len: 12,
+                    file: Some(1)
+                }
+            ),
+        ]
+    );
+
+    let mut image = DiskImage::decode(b"10, 12, 3, 11, 12", Encoding::Commas)?;
+    image.compact(Strategy::WholeFile(Fit::First));
+    assert_eq!(image.stats().holes, 0);
+    assert_eq!(
+        image.runs().nth(1),
+        Some((
+            10,
+            Run {
+                len: 12,
+                file: Some(2)
+            }
+        ))
+    );
+
+    assert_eq!(
+        DiskImage::decode(b"1,,2", Encoding::Commas).err(),
+        Some("`` isn't a length".to_string())
+    );
+    assert_eq!(
+        DiskImage::decode(&[0x01, 0x80], Encoding::Varint).err(),
+        Some("the disk map ends partway through a length".to_string())
+    );
+    assert_eq!(
+        DiskImage::decode(&[0xff; 11], Encoding::Varint).err(),
+        Some("length 0 is too long".to_string())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_compaction_matches_blocks() -> Result<(), Box<dyn std::error::Error>> {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(0x2024_1209);
+    let mut random = move |bound: u64| rng.gen_range(0..bound);
+
+    for _ in 0..300 {
+        // lengths past 9, so lots of size classes
+        let lens = (0..random(30))
+            .map(|_| random(25) as usize)
+            .collect::<Vec<_>>();
+        let map = lens.iter().map(|len| len.to_string()).collect::<Vec<_>>();
+        let image = DiskImage::decode(map.join(",").as_bytes(), Encoding::Commas)?;
+
+        for fit in [Fit::First, Fit::Best] {
+            // the disk a block at a time, each file moved by scanning it all
+            let mut blocks = lens
+                .iter()
+                .enumerate()
+                .flat_map(|(i, len)| vec![(i % 2 == 0).then_some(i / 2); *len])
+                .collect::<Vec<_>>();
+            for file in (0..lens.len().div_ceil(2)).rev() {
+                let from = blocks.iter().position(|b| *b == Some(file));
+                let (Some(from), len) = (from, lens[2 * file]) else {
+                    continue;
+                };
+
+                let mut holes = vec![];
+                let mut i = 0;
+                while i < from {
+                    let run = blocks[i..from].iter().take_while(|b| b.is_none()).count();
+                    if run >= len {
+                        holes.push((run, i));
+                    }
+                    i += run.max(1);
+                }
+                let hole = match fit {
+                    Fit::First => holes.first(),
+                    Fit::Best => holes.iter().min(),
+                };
+                if let Some((_, to)) = hole {
+                    blocks[from..from + len].fill(None);
+                    blocks[*to..*to + len].fill(Some(file));
+                }
+            }
+
+            let mut compacted = image.clone();
+            compacted.compact(Strategy::WholeFile(fit));
+            let expected = blocks
+                .iter()
+                .map(|b| b.map_or('.', |id| char::from(b'0' + (id % 10) as u8)))
+                .collect::<String>();
+            assert_eq!(compacted.to_string(), expected, "{fit:?} on {lens:?}");
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use std::{
     collections::{HashMap, HashSet},
+    ops::RangeInclusive,
     time::Instant,
 };
 
@@ -23,121 +24,266 @@ pub fn puzzle2() -> Result<usize, Box<dyn std::error::Error>> {
     ans
 }
 
+/// Prints every trail on the map, as the points along it
+pub fn trails() -> Result<(), Box<dyn std::error::Error>> {
+    let map = Map::parse(&std::fs::read_to_string("./inputs/dec10.txt")?)?;
+    let rules = Rules::aoc();
+    for trail in map.trails(&rules) {
+        println!("{trail:?}");
+    }
+
+    Ok(())
+}
+
 const DIRECTIONS: [Off; 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DIAGONALS: [Off; 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
 
-#[inline]
-fn get_pos(input: &[Vec<u32>], (mut i, mut j): Coord, off: Off) -> Option<(u32, Coord)> {
-    match off {
-        (-1, 0) => {
-            if i == 0 {
-                return None;
-            }
-            i -= 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tile {
+    Height(u8),
+    /// `.`, which no trail can cross
+    Impassable,
+}
+
+/// What makes a trail: it starts at height `start`, and every step heads
+/// for `end` by an amount in `step`, without going past it. A step of 0
+/// would let trails go round in circles, so it's never taken.
+#[derive(Debug, Clone)]
+pub(crate) struct Rules {
+    pub(crate) start: u8,
+    pub(crate) end: u8,
+    pub(crate) step: RangeInclusive<u8>,
+    /// whether steps can go diagonally, as well as up, down, left and right
+    pub(crate) diagonal: bool,
+}
+
+impl Rules {
+    /// from 0 to 9, a step up at a time
+    pub(crate) fn aoc() -> Self {
+        Self {
+            start: 0,
+            end: 9,
+            step: 1..=1,
+            diagonal: false,
         }
-        (1, 0) => {
-            if i + 1 == input.len() {
-                return None;
-            }
-            i += 1;
+    }
+
+    /// how much further there is to go from `height`, if it's on the way
+    fn remaining(&self, height: u8) -> Option<u8> {
+        if self.start <= self.end {
+            (self.start..=self.end)
+                .contains(&height)
+                .then(|| self.end - height)
+        } else {
+            (self.end..=self.start)
+                .contains(&height)
+                .then(|| height - self.end)
         }
-        (0, -1) => {
-            if j == 0 {
-                return None;
-            }
-            j -= 1;
+    }
+}
+
+/// A rectangular topographic map, rows top to bottom
+pub(crate) struct Map {
+    tiles: Vec<Vec<Tile>>,
+    width: usize,
+}
+
+impl Map {
+    pub(crate) fn parse(input: &str) -> Result<Self, String> {
+        let mut tiles = vec![];
+        for (i, line) in input.lines().enumerate() {
+            let row = line
+                .chars()
+                .enumerate()
+                .map(|(j, c)| match c {
+                    '0'..='9' => Ok(Tile::Height(c as u8 - b'0')),
+                    '.' => Ok(Tile::Impassable),
+                    _ => Err(format!(
+                        "unexpected `{c}` at line {}, column {}",
+                        i + 1,
+                        j + 1
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            tiles.push(row);
         }
-        (0, 1) => {
-            if j + 1 == input.len() {
-                return None;
-            }
-            j += 1;
+
+        let width = tiles.first().map_or(0, |row| row.len());
+        if let Some(i) = tiles.iter().position(|row| row.len() != width) {
+            return Err(format!(
+                "line {} is {} wide, not {width} like the first",
+                i + 1,
+                tiles[i].len()
+            ));
         }
-        _ => {
-            return None;
+
+        Ok(Self { tiles, width })
+    }
+
+    pub(crate) fn get(&self, (i, j): Coord) -> Option<Tile> {
+        self.tiles.get(i).and_then(|row| row.get(j)).copied()
+    }
+
+    fn height(&self, pos: Coord) -> Option<u8> {
+        match self.get(pos)? {
+            Tile::Height(height) => Some(height),
+            Tile::Impassable => None,
         }
     }
-    Some((input[i][j], (i, j)))
-}
 
-fn handle_puzzle1(input: &str) -> Result<usize, Box<dyn std::error::Error>> {
-    // Approach: expand a topological frontier
-
-    type ReachablePeaks = HashSet<Coord>;
-    type Frontier = HashMap<Coord, ReachablePeaks>;
-    let input = input
-        .lines()
-        .map(|line| {
-            line.chars()
-                .map(|c| c.to_digit(10).unwrap_or(u32::MAX))
-                .collect::<Vec<_>>()
-        })
-        .collect::<Vec<_>>();
-
-    let mut frontier = Frontier::new();
-    for (i, line) in input.iter().enumerate() {
-        for (j, level) in line.iter().enumerate() {
-            if *level == 9 {
-                frontier.entry((i, j)).or_default().insert((i, j));
-            }
+    /// every point a trail at `pos` can step to next
+    fn next_steps(&self, pos: Coord, rules: &Rules) -> Vec<Coord> {
+        let Some(remaining) = self.height(pos).and_then(|h| rules.remaining(h)) else {
+            return vec![];
+        };
+        let diagonals = if rules.diagonal { &DIAGONALS[..] } else { &[] };
+
+        DIRECTIONS
+            .iter()
+            .chain(diagonals)
+            .filter_map(|(di, dj)| {
+                let i = pos.0.checked_add_signed(*di as isize)?;
+                let j = pos.1.checked_add_signed(*dj as isize)?;
+                (i < self.tiles.len() && j < self.width).then_some((i, j))
+            })
+            .filter(|adj| {
+                let next = self.height(*adj).and_then(|h| rules.remaining(h));
+                next.is_some_and(|next| {
+                    next < remaining && rules.step.contains(&(remaining - next))
+                })
+            })
+            .collect()
+    }
+
+    /// every point a trail can start from, row by row
+    pub(crate) fn trailheads(&self, rules: &Rules) -> Vec<Coord> {
+        (0..self.tiles.len())
+            .flat_map(|i| (0..self.width).map(move |j| (i, j)))
+            .filter(|pos| self.height(*pos) == Some(rules.start))
+            .collect()
+    }
+
+    /// every point that's on the way from `rules.start` to `rules.end`,
+    /// those nearest the end first
+    fn towards_start(&self, rules: &Rules) -> Vec<Coord> {
+        let mut points = (0..self.tiles.len())
+            .flat_map(|i| (0..self.width).map(move |j| (i, j)))
+            .filter_map(|pos| {
+                let remaining = self.height(pos).and_then(|h| rules.remaining(h))?;
+                Some((remaining, pos))
+            })
+            .collect::<Vec<_>>();
+        points.sort();
+
+        points.into_iter().map(|(_, pos)| pos).collect()
+    }
+
+    /// How many ends each trailhead reaches, summed over the trailheads
+    pub(crate) fn score(&self, rules: &Rules) -> usize {
+        // Approach: spread the reachable ends back from the end, a point at a
+        // time, so each point's next steps are done before it is
+
+        type ReachableEnds = HashSet<Coord>;
+        let mut reachable = HashMap::<Coord, ReachableEnds>::new();
+        for pos in self.towards_start(rules) {
+            let ends = if self.height(pos) == Some(rules.end) {
+                ReachableEnds::from([pos])
+            } else {
+                self.next_steps(pos, rules)
+                    .iter()
+                    .flat_map(|adj| reachable[adj].iter().copied())
+                    .collect()
+            };
+            reachable.insert(pos, ends);
         }
+
+        self.trailheads(rules)
+            .iter()
+            .map(|head| reachable[head].len())
+            .sum()
     }
 
-    for target_level in (0..9).rev() {
-        let mut new_frontier = Frontier::new();
-        for (pos, nines) in frontier {
-            for dir in DIRECTIONS {
-                if let Some((adj_level, adj)) = get_pos(&input, pos, dir) {
-                    if adj_level == target_level {
-                        new_frontier.entry(adj).or_default().extend(nines.clone());
-                    }
-                }
-            }
+    /// How many distinct trails there are, from any trailhead
+    pub(crate) fn rating(&self, rules: &Rules) -> usize {
+        type NumPaths = usize;
+        let mut paths = HashMap::<Coord, NumPaths>::new();
+        for pos in self.towards_start(rules) {
+            let n = if self.height(pos) == Some(rules.end) {
+                1
+            } else {
+                self.next_steps(pos, rules)
+                    .iter()
+                    .map(|adj| paths[adj])
+                    .sum()
+            };
+            paths.insert(pos, n);
         }
-        frontier = new_frontier;
+
+        self.trailheads(rules).iter().map(|head| paths[head]).sum()
     }
 
-    Ok(frontier.values().map(|set| set.len()).sum())
+    /// Every trail, as the points along it, one trailhead at a time
+    pub(crate) fn trails<'m>(&'m self, rules: &'m Rules) -> Trails<'m> {
+        Trails {
+            map: self,
+            rules,
+            heads: self.trailheads(rules).into_iter(),
+            stack: vec![],
+        }
+    }
 }
 
-fn handle_puzzle2(input: &str) -> Result<usize, Box<dyn std::error::Error>> {
-    // Approach: expand a topological frontier
-
-    type NumPaths = usize;
-    type Frontier = HashMap<Coord, NumPaths>;
-
-    let input = input
-        .lines()
-        .map(|line| {
-            line.chars()
-                .map(|c| c.to_digit(10).unwrap_or(u32::MAX))
-                .collect::<Vec<_>>()
-        })
-        .collect::<Vec<_>>();
-
-    let mut frontier = Frontier::new();
-    for (i, line) in input.iter().enumerate() {
-        for (j, level) in line.iter().enumerate() {
-            if *level == 0 {
-                frontier.insert((i, j), 1);
-            }
-        }
+/// A depth-first walk along every trail, yielding each as it gets to the end
+pub(crate) struct Trails<'m> {
+    map: &'m Map,
+    rules: &'m Rules,
+    heads: std::vec::IntoIter<Coord>,
+    /// the trail so far, each point with the next steps it's yet to take
+    stack: Vec<(Coord, Vec<Coord>)>,
+}
+
+impl Trails<'_> {
+    /// takes a step to `pos`, returning the trail if that's the end of it
+    fn push(&mut self, pos: Coord) -> Option<Vec<Coord>> {
+        let mut next = self.map.next_steps(pos, self.rules);
+        next.reverse();
+        self.stack.push((pos, next));
+
+        (self.map.height(pos) == Some(self.rules.end))
+            .then(|| self.stack.iter().map(|(pos, _)| *pos).collect())
     }
+}
 
-    for target_level in 1..=9 {
-        let mut new_frontier = Frontier::new();
-        for (pos, paths) in frontier {
-            for dir in DIRECTIONS {
-                if let Some((adj_level, adj)) = get_pos(&input, pos, dir) {
-                    if adj_level == target_level {
-                        *new_frontier.entry(adj).or_default() += paths;
+impl Iterator for Trails<'_> {
+    type Item = Vec<Coord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let step = match self.stack.last_mut() {
+                Some((_, next)) => next.pop(),
+                None => Some(self.heads.next()?),
+            };
+
+            match step {
+                Some(pos) => {
+                    if let Some(trail) = self.push(pos) {
+                        return Some(trail);
                     }
                 }
+                None => {
+                    self.stack.pop();
+                }
             }
         }
-        frontier = new_frontier;
     }
+}
 
-    Ok(frontier.values().sum())
+fn handle_puzzle1(input: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    Ok(Map::parse(input)?.score(&Rules::aoc()))
+}
+
+fn handle_puzzle2(input: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    Ok(Map::parse(input)?.rating(&Rules::aoc()))
 }
 
 type Coord = (usize, usize);
@@ -239,3 +385,129 @@ fn test_puzzle2() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_trails() -> Result<(), Box<dyn std::error::Error>> {
+    let input = r#".....0.
+..4321.
+..5..2.
+..6543.
+..7..4.
+..8765.
+..9...."#;
+
+    let map = Map::parse(input)?;
+    let rules = Rules::aoc();
+    let trails = map.trails(&rules).collect::<Vec<_>>();
+    assert_eq!(trails.len(), map.rating(&rules));
+    assert_eq!(
+        trails[0],
+        vec![
+            (0, 5),
+            (1, 5),
+            (2, 5),
+            (3, 5),
+            (4, 5),
+            (5, 5),
+            (5, 4),
+            (5, 3),
+            (5, 2),
+            (6, 2)
+        ]
+    );
+    assert!(trails.iter().all(|trail| trail.last() == Some(&(6, 2))));
+
+    // wider than it's tall, and the other way round
+    let map = Map::parse("0123456789")?;
+    assert_eq!((map.score(&rules), map.rating(&rules)), (1, 1));
+    let map = Map::parse(
+        &"0123456789"
+            .chars()
+            .map(String::from)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )?;
+    assert_eq!((map.score(&rules), map.rating(&rules)), (1, 1));
+
+    assert_eq!(
+        Map::parse("0123\n45x7").err(),
+        Some("unexpected `x` at line 2, column 3".to_string())
+    );
+    assert_eq!(
+        Map::parse("0123\n456").err(),
+        Some("line 2 is 3 wide, not 4 like the first".to_string())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_rules() -> Result<(), Box<dyn std::error::Error>> {
+    let count = |input: &str, rules: &Rules| -> Result<_, String> {
+        let map = Map::parse(input)?;
+        Ok((
+            map.score(rules),
+            map.rating(rules),
+            map.trails(rules).count(),
+        ))
+    };
+
+    // downhill, from the 9s, though the one in the corner is boxed in
+    // by another
+    let descent = Rules {
+        start: 9,
+        end: 0,
+        ..Rules::aoc()
+    };
+    assert_eq!(count("0123456789\n1234567899", &descent)?, (2, 10, 10));
+
+    // two at a time, or one or two
+    let by_two = Rules {
+        end: 8,
+        step: 2..=2,
+        ..Rules::aoc()
+    };
+    assert_eq!(count("02468\n.1357", &by_two)?, (1, 1, 1));
+    let by_one_or_two = Rules {
+        end: 4,
+        step: 1..=2,
+        ..Rules::aoc()
+    };
+    assert_eq!(count("013\n124", &by_one_or_two)?, (1, 3, 3));
+    assert_eq!(
+        count(
+            "013\n124",
+            &Rules {
+                end: 4,
+                ..Rules::aoc()
+            }
+        )?,
+        (0, 0, 0)
+    );
+
+    // a diagonal staircase, and a step of 0 going nowhere
+    let diagonal = Rules {
+        end: 2,
+        diagonal: true,
+        ..Rules::aoc()
+    };
+    assert_eq!(count("0.2\n.1.", &diagonal)?, (1, 1, 1));
+    assert_eq!(
+        count(
+            "0.2\n.1.",
+            &Rules {
+                diagonal: false,
+                ..diagonal.clone()
+            }
+        )?,
+        (0, 0, 0)
+    );
+    let flat = Rules {
+        end: 0,
+        step: 0..=1,
+        ..Rules::aoc()
+    };
+    assert_eq!(count("00\n00", &flat)?, (4, 4, 4));
+
+    Ok(())
+}
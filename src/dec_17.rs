@@ -1,11 +1,29 @@
+mod dec_17_analysis;
+mod dec_17_compiled;
 mod dec_17_part1;
 mod dec_17_part2;
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let input = std::fs::read_to_string("./inputs/dec17.txt")?;
 
+    let (vm, quine_tgt) = dec_17_part1::parse::<usize>(input.as_str())?;
+    match dec_17_analysis::LoopSummary::analyse(&vm.instructions) {
+        Ok(summary) => {
+            println!("{summary}\n");
+            let a = vm.registers[dec_17_part1::REG_A] as u64;
+            if let Some(outputs) = summary.closed_form(a) {
+                let outputs = outputs.iter().map(u64::to_string).collect::<Vec<_>>();
+                println!(
+                    "Puzzle 1 from the closed form: ans {:?}\n",
+                    outputs.join(",")
+                );
+            }
+        }
+        Err(e) => println!("Couldn't decompile the program: {e}\n"),
+    }
+
     let now = std::time::Instant::now();
-    let result = dec_17_part1::handle_puzzle1(input.as_str());
+    let result = dec_17_part1::handle_puzzle1(input.as_str())?;
     println!(
         "Puzzle 1: ans {:?}, ({} us)",
         result,
@@ -13,12 +31,37 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     let now = std::time::Instant::now();
-    let result = dec_17_part2::handle_puzzle2(input.as_str());
+    let result = dec_17_part2::handle_puzzle2(input.as_str())?;
     println!(
         "Puzzle 2: ans {:?}, ({} us)",
         result,
         now.elapsed().as_micros()
     );
 
+    // the same search on both backends, to show what compiling buys
+    let program = quine_tgt
+        .split(',')
+        .map(|x| x.parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let now = std::time::Instant::now();
+    let interpreted = dec_17_compiled::search_quine(&vm.instructions, &program, |a, tail| {
+        let mut vm = vm.clone();
+        vm.registers[dec_17_part1::REG_A] = a;
+        while vm.exec() {}
+
+        vm.output.iter().map(|x| *x as u8).eq(tail.iter().copied())
+    })?;
+    let interpreted_us = now.elapsed().as_micros();
+    println!("\tinterpreted search: ans {interpreted:?}, ({interpreted_us} us)");
+
+    let now = std::time::Instant::now();
+    let compiled = dec_17_compiled::handle_puzzle2(input.as_str())?;
+    let compiled_us = now.elapsed().as_micros();
+    println!(
+        "\tcompiled search: ans {compiled:?}, ({compiled_us} us, {:.1}x faster)",
+        interpreted_us as f64 / compiled_us.max(1) as f64
+    );
+
     Ok(())
 }
@@ -0,0 +1,342 @@
+use std::fmt;
+
+use crate::dec_17_part1::{Instr, REG_A, REG_B, REG_C};
+
+/// A register's value part way through an iteration, in terms of the
+/// registers as they were at the top of the loop
+#[derive(Clone, PartialEq)]
+pub(crate) enum Expr {
+    Reg(usize),
+    Lit(u64),
+    Xor(Box<Expr>, Box<Expr>),
+    Mod8(Box<Expr>),
+    Shr(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// `None` for the reserved combo operand 7
+    fn combo(x: u8, regs: &[Expr]) -> Option<Expr> {
+        match x {
+            x if x < 4 => Some(Expr::Lit(x as u64)),
+            x if x < 7 => Some(regs[(x - 4) as usize].clone()),
+            _ => None,
+        }
+    }
+
+    /// xor, folding every literal in the chain into one trailing literal
+    fn xor(self, other: Expr) -> Expr {
+        let mut terms = self.xor_terms();
+        terms.extend(other.xor_terms());
+
+        let lit = terms
+            .iter()
+            .filter_map(|t| match t {
+                Expr::Lit(x) => Some(*x),
+                _ => None,
+            })
+            .fold(0, |acc, x| acc ^ x);
+        terms.retain(|t| !matches!(t, Expr::Lit(_)));
+        if lit != 0 || terms.is_empty() {
+            terms.push(Expr::Lit(lit));
+        }
+
+        terms
+            .into_iter()
+            .reduce(|acc, t| Expr::Xor(Box::new(acc), Box::new(t)))
+            .unwrap()
+    }
+
+    fn mod8(self) -> Expr {
+        match self {
+            Expr::Lit(x) => Expr::Lit(x % 8),
+            Expr::Mod8(_) => self,
+            _ => Expr::Mod8(Box::new(self)),
+        }
+    }
+
+    fn shr(self, amount: Expr) -> Expr {
+        match (self, amount) {
+            (Expr::Lit(x), Expr::Lit(y)) => Expr::Lit(x.checked_shr(y as u32).unwrap_or(0)),
+            (x, Expr::Lit(0)) => x,
+            // (x >> a) >> b = x >> (a + b)
+            (Expr::Shr(x, a), Expr::Lit(b)) if matches!(*a, Expr::Lit(_)) => {
+                let Expr::Lit(a) = *a else { unreachable!() };
+                Expr::Shr(x, Box::new(Expr::Lit(a + b)))
+            }
+            (x, y) => Expr::Shr(Box::new(x), Box::new(y)),
+        }
+    }
+
+    fn xor_terms(self) -> Vec<Expr> {
+        match self {
+            Expr::Xor(a, b) => [a.xor_terms(), b.xor_terms()].concat(),
+            _ => vec![self],
+        }
+    }
+
+    /// whether the expression reads register `reg` as it was at the top of the loop
+    pub(crate) fn reads(&self, reg: usize) -> bool {
+        match self {
+            Expr::Reg(r) => *r == reg,
+            Expr::Lit(_) => false,
+            Expr::Mod8(x) => x.reads(reg),
+            Expr::Xor(a, b) | Expr::Shr(a, b) => a.reads(reg) || b.reads(reg),
+        }
+    }
+
+    pub(crate) fn eval(&self, regs: &[u64]) -> u64 {
+        match self {
+            Expr::Reg(r) => regs[*r],
+            Expr::Lit(x) => *x,
+            Expr::Xor(a, b) => a.eval(regs) ^ b.eval(regs),
+            Expr::Mod8(x) => x.eval(regs) % 8,
+            Expr::Shr(a, b) => {
+                let shift = u32::try_from(b.eval(regs)).unwrap_or(u32::MAX);
+                a.eval(regs).checked_shr(shift).unwrap_or(0)
+            }
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Reg(_) | Expr::Lit(_) => write!(f, "{self}"),
+            _ => write!(f, "({self})"),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Reg(r) => write!(f, "{}", ["A", "B", "C"][*r]),
+            Expr::Lit(x) => write!(f, "{x}"),
+            Expr::Xor(a, b) => {
+                match a.as_ref() {
+                    Expr::Xor(_, _) => write!(f, "{a}")?,
+                    _ => a.fmt_operand(f)?,
+                }
+                write!(f, " ^ ")?;
+                b.fmt_operand(f)
+            }
+            Expr::Mod8(x) => {
+                x.fmt_operand(f)?;
+                write!(f, " % 8")
+            }
+            Expr::Shr(a, b) => {
+                a.fmt_operand(f)?;
+                write!(f, " >> ")?;
+                b.fmt_operand(f)
+            }
+        }
+    }
+}
+
+/// Why a program isn't a single "output something, shift A, loop" body, or
+/// isn't one `search_quine` can search
+#[derive(Debug, PartialEq)]
+pub(crate) enum Unsupported {
+    Empty,
+    NoTrailingJump,
+    JumpNotToStart(u8),
+    InnerJump(usize),
+    ShiftCount(usize),
+    NonConstantShift(u8),
+    OutputCount(usize),
+    ReservedOperand(usize),
+    ShiftAmount(u8),
+    NotMemoryless,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsupported::Empty => write!(f, "the program is empty"),
+            Unsupported::NoTrailingJump => write!(f, "the program doesn't end in `jnz`"),
+            Unsupported::JumpNotToStart(x) => {
+                write!(f, "the final `jnz` goes to {x} instead of 0")
+            }
+            Unsupported::InnerJump(i) => write!(f, "instruction {i} jumps inside the loop"),
+            Unsupported::ShiftCount(n) => write!(f, "A is shifted {n} times, not once"),
+            Unsupported::NonConstantShift(x) => {
+                write!(
+                    f,
+                    "A is shifted by combo operand {x}, which isn't a literal"
+                )
+            }
+            Unsupported::OutputCount(n) => write!(f, "the loop outputs {n} times, not once"),
+            Unsupported::ReservedOperand(i) => {
+                write!(f, "instruction {i} reads the reserved combo operand 7")
+            }
+            Unsupported::ShiftAmount(x) => {
+                write!(f, "A is shifted by {x} bits, not one octal digit")
+            }
+            Unsupported::NotMemoryless => {
+                write!(f, "the output reads B or C from the previous iteration")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// A day 17 program decompiled into `do { out(output); A >>= a_shift } while A != 0`
+pub(crate) struct LoopSummary {
+    pub(crate) a_shift: u8,
+    /// the value printed each iteration, in terms of the registers at the top
+    /// of the iteration
+    pub(crate) output: Expr,
+}
+
+impl LoopSummary {
+    pub(crate) fn analyse(instructions: &[Instr]) -> Result<Self, Unsupported> {
+        let (last, body) = instructions.split_last().ok_or(Unsupported::Empty)?;
+        match last {
+            Instr::Jnz(0) => {}
+            Instr::Jnz(x) => return Err(Unsupported::JumpNotToStart(*x)),
+            _ => return Err(Unsupported::NoTrailingJump),
+        }
+
+        let mut regs = vec![Expr::Reg(REG_A), Expr::Reg(REG_B), Expr::Reg(REG_C)];
+        let mut shifts = vec![];
+        let mut outputs = vec![];
+
+        for (i, instr) in body.iter().enumerate() {
+            let combo =
+                |x: u8, regs: &[Expr]| Expr::combo(x, regs).ok_or(Unsupported::ReservedOperand(i));
+
+            match instr {
+                Instr::Adv(x) => {
+                    shifts.push(*x);
+                    regs[REG_A] = regs[REG_A].clone().shr(combo(*x, &regs)?);
+                }
+                Instr::Bdv(x) => regs[REG_B] = regs[REG_A].clone().shr(combo(*x, &regs)?),
+                Instr::Cdv(x) => regs[REG_C] = regs[REG_A].clone().shr(combo(*x, &regs)?),
+                Instr::Bxl(x) => regs[REG_B] = regs[REG_B].clone().xor(Expr::Lit(*x as u64)),
+                Instr::Bst(x) => regs[REG_B] = combo(*x, &regs)?.mod8(),
+                Instr::Bxc => regs[REG_B] = regs[REG_B].clone().xor(regs[REG_C].clone()),
+                Instr::Out(x) => outputs.push(combo(*x, &regs)?.mod8()),
+                Instr::Jnz(_) => return Err(Unsupported::InnerJump(i)),
+            }
+        }
+
+        let a_shift = match shifts.as_slice() {
+            [x] if *x < 4 => *x,
+            [x] => return Err(Unsupported::NonConstantShift(*x)),
+            _ => return Err(Unsupported::ShiftCount(shifts.len())),
+        };
+
+        if outputs.len() != 1 {
+            return Err(Unsupported::OutputCount(outputs.len()));
+        }
+
+        Ok(Self {
+            a_shift,
+            output: outputs.remove(0),
+        })
+    }
+
+    /// true if each output depends only on A, so that `out[i] = f(A >> shift * i)`
+    pub(crate) fn is_memoryless(&self) -> bool {
+        !self.output.reads(REG_B) && !self.output.reads(REG_C)
+    }
+
+    /// What the program prints when A starts as `a`, from the summary alone.
+    /// `None` unless it `is_memoryless` and A shrinks, so the loop ends.
+    pub(crate) fn closed_form(&self, a: u64) -> Option<Vec<u64>> {
+        if !self.is_memoryless() || self.a_shift == 0 {
+            return None;
+        }
+
+        // the body runs once before A is first checked
+        let outputs = (0..)
+            .map(|i| a.checked_shr(self.a_shift as u32 * i).unwrap_or(0))
+            .enumerate()
+            .take_while(|(i, a)| *i == 0 || *a != 0)
+            .map(|(_, a)| self.output.eval(&[a, 0, 0]))
+            .collect();
+
+        Some(outputs)
+    }
+}
+
+impl fmt::Display for LoopSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "do {{")?;
+        writeln!(f, "    out({})", self.output)?;
+        writeln!(f, "    A >>= {}", self.a_shift)?;
+        write!(f, "}} while A != 0")?;
+
+        if self.is_memoryless() {
+            write!(
+                f,
+                "\n\ni.e. out[i] = f(A >> {}i), where f(A) = {}",
+                self.a_shift, self.output
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_analyse() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::dec_17_part1::parse;
+
+    let input = r#"Register A: 0
+Register B: 0
+Register C: 0
+
+Program: 2,4,1,1,7,5,4,6,0,3,1,4,5,5,3,0"#;
+
+    let (vm, _) = parse::<usize>(input)?;
+    let summary = LoopSummary::analyse(&vm.instructions)?;
+
+    assert_eq!(summary.a_shift, 3);
+    assert!(summary.is_memoryless());
+    assert_eq!(
+        summary.output.to_string(),
+        "((A % 8) ^ (A >> ((A % 8) ^ 1)) ^ 5) % 8"
+    );
+
+    // the closed form agrees with actually running the program
+    for a in [0, 1, 7, 8, 117440, 0o1234567, 0xdead_beef, usize::MAX >> 3] {
+        let mut vm = vm.clone();
+        vm.registers[REG_A] = a;
+        while vm.exec() {}
+
+        let closed_form = summary.closed_form(a as u64).unwrap();
+        assert!(vm.output.iter().map(|x| *x as u64).eq(closed_form));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_unsupported() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::dec_17_part1::parse;
+
+    let analyse = |program: &str| {
+        let input = format!("Register A: 0\nRegister B: 0\nRegister C: 0\n\nProgram: {program}");
+        let (vm, _) = parse::<usize>(&input).unwrap();
+        LoopSummary::analyse(&vm.instructions).map(|s| s.output.to_string())
+    };
+
+    assert_eq!(analyse("0,3,5,4,3,0"), Ok("(A >> 3) % 8".to_string()));
+    assert_eq!(analyse("5,4,0,3"), Err(Unsupported::NoTrailingJump));
+    assert_eq!(analyse("5,4,0,3,3,2"), Err(Unsupported::JumpNotToStart(2)));
+    assert_eq!(analyse("3,0,5,4,0,3,3,0"), Err(Unsupported::InnerJump(0)));
+    assert_eq!(
+        analyse("5,4,0,4,3,0"),
+        Err(Unsupported::NonConstantShift(4))
+    );
+    assert_eq!(analyse("0,1,0,2,5,4,3,0"), Err(Unsupported::ShiftCount(2)));
+    assert_eq!(analyse("0,3,3,0"), Err(Unsupported::OutputCount(0)));
+
+    // combo operand 7 is reserved, so `out 7` has nothing to print
+    assert_eq!(
+        LoopSummary::analyse(&[Instr::Out(7), Instr::Adv(3), Instr::Jnz(0)]).err(),
+        Some(Unsupported::ReservedOperand(0))
+    );
+
+    Ok(())
+}
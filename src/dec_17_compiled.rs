@@ -0,0 +1,362 @@
+use crate::dec_17_analysis::{LoopSummary, Unsupported};
+use crate::dec_17_part1::{parse, Instr, REG_A, REG_B, REG_C};
+
+/// What a compiled instruction asks the run loop to do next
+#[derive(Clone, Copy)]
+enum Effect {
+    Next,
+    Jump(usize),
+    Out(u8),
+    Overflow,
+}
+
+type Step = Box<dyn Fn(&mut [usize; 3]) -> Effect + Send + Sync>;
+
+/// Why `Compiled::run` returned
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Outcome {
+    /// the instruction pointer ran off the end of the program
+    Halted,
+    /// the output sink asked to stop
+    Stopped,
+    /// `max_steps` instructions ran without halting
+    StepLimit,
+    /// a `dv` instruction shifted by 64 or more, which the reference
+    /// interpreter can't do for `usize` registers either
+    Overflow,
+}
+
+/// A day 17 program turned into one closure per instruction, with every
+/// opcode and combo operand resolved up front, so searching over millions of
+/// starting registers doesn't pay for `Instr::exec`'s dispatch or a `Vec` of
+/// output per run. Jump targets index instructions, as in `Instr::exec`.
+pub(crate) struct Compiled {
+    steps: Vec<Step>,
+}
+
+impl Compiled {
+    /// `None` if any instruction that reads a combo operand uses the reserved 7
+    pub(crate) fn compile(instructions: &[Instr]) -> Option<Self> {
+        let steps = instructions
+            .iter()
+            .map(|instr| Compiled::step(*instr))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self { steps })
+    }
+
+    fn step(instr: Instr) -> Option<Step> {
+        let step: Step = match instr {
+            Instr::Adv(x) => Compiled::div(x, REG_A)?,
+            Instr::Bdv(x) => Compiled::div(x, REG_B)?,
+            Instr::Cdv(x) => Compiled::div(x, REG_C)?,
+            Instr::Bxl(x) => Box::new(move |r| {
+                r[REG_B] ^= x as usize;
+                Effect::Next
+            }),
+            Instr::Bst(x) if x < 4 => Box::new(move |r| {
+                r[REG_B] = x as usize;
+                Effect::Next
+            }),
+            Instr::Bst(x) if x < 7 => {
+                let src = (x - 4) as usize;
+                Box::new(move |r| {
+                    r[REG_B] = r[src] % 8;
+                    Effect::Next
+                })
+            }
+            Instr::Jnz(x) => Box::new(move |r| {
+                if r[REG_A] != 0 {
+                    Effect::Jump(x as usize)
+                } else {
+                    Effect::Next
+                }
+            }),
+            Instr::Bxc => Box::new(|r| {
+                r[REG_B] ^= r[REG_C];
+                Effect::Next
+            }),
+            Instr::Out(x) if x < 4 => Box::new(move |_| Effect::Out(x)),
+            Instr::Out(x) if x < 7 => {
+                let src = (x - 4) as usize;
+                Box::new(move |r| Effect::Out((r[src] % 8) as u8))
+            }
+            Instr::Bst(_) | Instr::Out(_) => return None,
+        };
+
+        Some(step)
+    }
+
+    fn div(x: u8, dst: usize) -> Option<Step> {
+        let step: Step = match x {
+            x if x < 4 => Box::new(move |r| {
+                r[dst] = r[REG_A] >> x;
+                Effect::Next
+            }),
+            x if x < 7 => {
+                let src = (x - 4) as usize;
+                Box::new(move |r| match u32::try_from(r[src]) {
+                    Ok(shift) if shift < usize::BITS => {
+                        r[dst] = r[REG_A] >> shift;
+                        Effect::Next
+                    }
+                    _ => Effect::Overflow,
+                })
+            }
+            _ => return None,
+        };
+
+        Some(step)
+    }
+
+    /// Runs from the first instruction until the program halts, `sink`
+    /// returns `false`, or `max_steps` instructions have run.
+    pub(crate) fn run(
+        &self,
+        registers: &mut [usize; 3],
+        max_steps: usize,
+        mut sink: impl FnMut(u8) -> bool,
+    ) -> Outcome {
+        let mut ip = 0;
+
+        for _ in 0..max_steps {
+            let Some(step) = self.steps.get(ip) else {
+                return Outcome::Halted;
+            };
+
+            match step(registers) {
+                Effect::Next => ip += 1,
+                Effect::Jump(x) => ip = x,
+                Effect::Out(x) => {
+                    ip += 1;
+                    if !sink(x) {
+                        return Outcome::Stopped;
+                    }
+                }
+                Effect::Overflow => return Outcome::Overflow,
+            }
+        }
+
+        if ip < self.steps.len() {
+            Outcome::StepLimit
+        } else {
+            Outcome::Halted
+        }
+    }
+
+    /// the full output of a run starting from `registers`, or `None` if it
+    /// overflows or doesn't halt within `max_steps`
+    #[cfg(test)]
+    pub(crate) fn output(&self, mut registers: [usize; 3], max_steps: usize) -> Option<Vec<u8>> {
+        let mut output = vec![];
+        match self.run(&mut registers, max_steps, |x| {
+            output.push(x);
+            true
+        }) {
+            Outcome::Halted => Some(output),
+            _ => None,
+        }
+    }
+
+    /// whether a run from `registers` prints exactly `expected` and halts,
+    /// giving up at the first output that differs
+    pub(crate) fn prints(
+        &self,
+        mut registers: [usize; 3],
+        expected: &[u8],
+        max_steps: usize,
+    ) -> bool {
+        let mut seen = 0;
+        let outcome = self.run(&mut registers, max_steps, |x| {
+            let matches = expected.get(seen) == Some(&x);
+            seen += 1;
+            matches
+        });
+
+        outcome == Outcome::Halted && seen == expected.len()
+    }
+}
+
+/// Finds the smallest A for which `prints(a, tail)` holds for the whole
+/// program, building A three bits at a time from its most significant end.
+/// That only works for the loops `LoopSummary` decompiles that shift A by 3
+/// and print something of A alone: output `i` then depends on A's octal
+/// digits from the `i`th up, so a candidate with `k` digits has to print the
+/// last `k` numbers of the program. Any other program is `Unsupported`.
+pub(crate) fn search_quine(
+    instructions: &[Instr],
+    program: &[u8],
+    mut prints: impl FnMut(usize, &[u8]) -> bool,
+) -> Result<Option<usize>, Unsupported> {
+    fn extend(
+        a: usize,
+        k: usize,
+        program: &[u8],
+        prints: &mut impl FnMut(usize, &[u8]) -> bool,
+    ) -> Option<usize> {
+        if k == 0 {
+            return Some(a);
+        }
+
+        let tail = &program[k - 1..];
+        for candidate in (0..8).map(|digit| (a << 3) | digit) {
+            if candidate != 0 && prints(candidate, tail) {
+                if let Some(a) = extend(candidate, k - 1, program, prints) {
+                    return Some(a);
+                }
+            }
+        }
+
+        None
+    }
+
+    let summary = LoopSummary::analyse(instructions)?;
+    if summary.a_shift != 3 {
+        return Err(Unsupported::ShiftAmount(summary.a_shift));
+    }
+    if !summary.is_memoryless() {
+        return Err(Unsupported::NotMemoryless);
+    }
+
+    if program.len() > (usize::BITS / 3) as usize {
+        return Ok(None);
+    }
+
+    Ok(extend(0, program.len(), program, &mut prints))
+}
+
+/// Part 2 by brute force on the compiled backend, for comparison with the
+/// symbolic solver in `dec_17_part2`
+pub(crate) fn handle_puzzle2(input: &str) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+    const MAX_STEPS: usize = 1 << 16;
+
+    let (vm, quine_tgt) = parse::<usize>(input)?;
+    let Some(compiled) = Compiled::compile(&vm.instructions) else {
+        return Ok(None);
+    };
+    let program = quine_tgt
+        .split(',')
+        .map(|x| x.parse::<u8>().unwrap())
+        .collect::<Vec<_>>();
+
+    let a = search_quine(&vm.instructions, &program, |a, tail| {
+        compiled.prints(
+            [a, vm.registers[REG_B], vm.registers[REG_C]],
+            tail,
+            MAX_STEPS,
+        )
+    })?;
+
+    Ok(a)
+}
+
+#[test]
+fn test_compiled() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::dec_17_part1::parse;
+
+    let input = r#"Register A: 729
+Register B: 0
+Register C: 0
+
+Program: 0,1,5,4,3,0"#;
+
+    let (vm, _) = parse::<usize>(input)?;
+    let compiled = Compiled::compile(&vm.instructions).unwrap();
+
+    assert_eq!(
+        compiled.output([729, 0, 0], 1000),
+        Some(vec![4, 6, 3, 5, 6, 3, 5, 2, 1, 0])
+    );
+
+    // stopping at the first output that doesn't match is what makes searches cheap
+    let mut seen = 0;
+    let outcome = compiled.run(&mut [729, 0, 0], 1000, |x| {
+        seen += 1;
+        x == 4
+    });
+    assert_eq!((outcome, seen), (Outcome::Stopped, 2));
+
+    assert!(compiled.prints([729, 0, 0], &[4, 6, 3, 5, 6, 3, 5, 2, 1, 0], 1000));
+    assert!(!compiled.prints([729, 0, 0], &[4, 6, 3], 1000));
+    assert!(!compiled.prints([729, 0, 0], &[4, 6, 3, 5, 6, 3, 5, 2, 1, 0, 0], 1000));
+
+    Ok(())
+}
+
+#[test]
+fn test_search_quine() -> Result<(), Box<dyn std::error::Error>> {
+    let input = r#"Register A: 0
+Register B: 0
+Register C: 0
+
+Program: 2,4,1,1,7,5,4,6,0,3,1,4,5,5,3,0"#;
+
+    let searched = handle_puzzle2(input)?;
+    assert_eq!(searched, crate::dec_17_part2::handle_puzzle2(input)?);
+
+    let example = r#"Register A: 2024
+Register B: 0
+Register C: 0
+
+Program: 0,3,5,4,3,0"#;
+    assert_eq!(handle_puzzle2(example)?, Some(117440));
+
+    Ok(())
+}
+
+#[test]
+fn test_compiled_matches_interpreter() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::dec_17_part1::{InstrIteratorExt, Vm};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(0x2024_1217);
+    let mut random = move |bound: u64| rng.gen_range(0..bound);
+
+    const MAX_STEPS: usize = 500;
+    let mut compared = 0;
+
+    for _ in 0..2000 {
+        let len = 1 + random(8) as usize;
+        let program = (0..len)
+            .flat_map(|_| {
+                let opcode = random(8) as u8;
+                // only bxl and jnz take a literal 7; everything else would be reserved
+                let operand = random(if matches!(opcode, 1 | 3) { 8 } else { 7 }) as u8;
+                [opcode, operand]
+            })
+            .collect::<Vec<_>>();
+        let instructions = program.into_iter().to_instr_iter().collect::<Vec<_>>();
+        let registers = [
+            random(1 << 20) as usize,
+            random(64) as usize,
+            random(64) as usize,
+        ];
+
+        let compiled = Compiled::compile(&instructions).unwrap();
+        let mut compiled_registers = registers;
+        let mut compiled_output = vec![];
+        let outcome = compiled.run(&mut compiled_registers, MAX_STEPS, |x| {
+            compiled_output.push(x as usize);
+            true
+        });
+
+        // the interpreter would panic on the shift, so there's nothing to compare
+        if outcome == Outcome::Overflow {
+            continue;
+        }
+
+        let mut vm = Vm::new(instructions, registers.to_vec());
+        let mut steps = 0;
+        while steps < MAX_STEPS && vm.exec() {
+            steps += 1;
+        }
+
+        assert_eq!(vm.output, compiled_output);
+        assert_eq!(vm.registers, compiled_registers);
+        compared += 1;
+    }
+
+    assert!(compared > 1000, "only compared {compared} programs");
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::{error::Error, str::FromStr};
+use std::error::Error;
 
 #[derive(Clone, Copy)]
 pub(crate) enum Instr {
@@ -18,6 +18,31 @@ pub(crate) static REG_B: usize = 1;
 
 pub(crate) static REG_C: usize = 2;
 
+/// A divisor of the form `2^k`. The VM only ever takes a register `% 8`, and
+/// restricting remainders to powers of two lets a symbolic register answer
+/// with its low `k` bits instead of needing a general division.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PowerOfTwo {
+    exponent: u32,
+}
+
+impl PowerOfTwo {
+    pub(crate) const EIGHT: Self = Self { exponent: 3 };
+
+    /// the number of low bits a remainder by this keeps
+    pub(crate) fn bits(self) -> usize {
+        self.exponent as usize
+    }
+}
+
+impl std::ops::Rem<PowerOfTwo> for usize {
+    type Output = usize;
+
+    fn rem(self, rhs: PowerOfTwo) -> Self::Output {
+        self & ((1 << rhs.exponent) - 1)
+    }
+}
+
 impl Instr {
     pub(crate) fn combo<R>(x: u8, reg: &[R]) -> R
     where
@@ -35,7 +60,7 @@ impl Instr {
         R: From<u8>
             + Clone
             + std::ops::BitXor<R, Output = R>
-            + std::ops::Rem<usize, Output = R>
+            + std::ops::Rem<PowerOfTwo, Output = R>
             + std::ops::Shr<Output = R>
             + std::ops::BitXorAssign<R>
             + std::cmp::PartialEq<usize>,
@@ -47,7 +72,7 @@ impl Instr {
                 *reg_b ^= From::from(*x);
             }
             Instr::Bst(x) => {
-                let bot_three_bits = Instr::combo(*x, &vm.registers) % 8;
+                let bot_three_bits = Instr::combo(*x, &vm.registers) % PowerOfTwo::EIGHT;
                 vm.registers[REG_B] = bot_three_bits;
             }
             Instr::Jnz(x) => {
@@ -62,7 +87,7 @@ impl Instr {
                 *reg_b = reg_b.clone() ^ reg_c;
             }
             Instr::Out(x) => {
-                let output = Instr::combo(*x, &vm.registers) % 8;
+                let output = Instr::combo(*x, &vm.registers) % PowerOfTwo::EIGHT;
                 vm.output.push(output);
             }
             Instr::Bdv(x) => Instr::div(*x, vm, REG_B),
@@ -136,7 +161,7 @@ where
     R: From<u8>
         + Clone
         + std::ops::BitXor<R, Output = R>
-        + std::ops::Rem<usize, Output = R>
+        + std::ops::Rem<PowerOfTwo, Output = R>
         + std::ops::Shr<Output = R>
         + std::ops::BitXorAssign<R>
         + std::cmp::PartialEq<usize>,
@@ -170,74 +195,178 @@ where
 
 pub(crate) type ParseOutput<R> = (Vm<R>, String);
 
-pub(crate) fn parse<R>(input: &str) -> ParseOutput<R>
+/// A malformed line in a day 17 input; `line` counts from 1
+#[derive(Debug, PartialEq)]
+pub(crate) struct ParseError {
+    pub(crate) line: usize,
+    pub(crate) reason: String,
+}
+
+impl ParseError {
+    fn new(line: usize, reason: impl Into<String>) -> Self {
+        Self {
+            line,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses any number of `Register X: n` lines, in any order, and one
+/// `Program: ...` line. Registers are lettered from `A`, and any the input
+/// leaves out (including `A` through `C`) start at 0. Opcodes and operands
+/// must be 0-7 and pair up, and no combo operand may be the reserved 7.
+pub(crate) fn parse<R>(input: &str) -> Result<ParseOutput<R>, ParseError>
 where
     R: From<u8>
         + From<usize>
         + Clone
         + std::ops::BitXor<R, Output = R>
-        + std::ops::Rem<usize, Output = R>
+        + std::ops::Rem<PowerOfTwo, Output = R>
         + std::ops::Shr<Output = R>
         + std::ops::BitXorAssign<R>
         + std::cmp::PartialEq<usize>,
 {
-    let mut lines = input.lines();
-
-    let reg_a = lines
-        .next()
-        .unwrap()
-        .split_once(": ")
-        .unwrap()
-        .1
-        .parse::<usize>()
-        .unwrap();
-
-    let reg_b = lines
-        .next()
-        .unwrap()
-        .split_once(": ")
-        .unwrap()
-        .1
-        .parse::<usize>()
-        .unwrap();
-
-    let reg_c = lines
-        .next()
-        .unwrap()
-        .split_once(": ")
-        .unwrap()
-        .1
-        .parse::<usize>()
-        .unwrap();
-
-    let registers = vec![From::from(reg_a), From::from(reg_b), From::from(reg_c)];
-
-    lines.next();
-
-    let instructions = lines.next().unwrap().split_once(": ").unwrap().1;
-    let quine_tgt = instructions;
-
-    let instructions = instructions
-        .split(",")
-        .map(|num| num.parse::<u8>().unwrap())
-        .to_instr_iter()
-        .collect::<Vec<_>>();
-
-    (Vm::new(instructions, registers), quine_tgt.to_string())
+    let mut registers: Vec<Option<usize>> = vec![None; 3];
+    let mut program: Option<(usize, Vec<u8>)> = None;
+
+    for (i, line) in input.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = line.split_once(':').ok_or_else(|| {
+            ParseError::new(line_no, format!("expected `key: value`, got `{line}`"))
+        })?;
+        let key = key.split_whitespace().collect::<Vec<_>>();
+        let value = value.trim();
+
+        match key.as_slice() {
+            ["Register", name] => {
+                let index = match name.as_bytes() {
+                    [letter @ b'A'..=b'Z'] => (letter - b'A') as usize,
+                    _ => {
+                        return Err(ParseError::new(
+                            line_no,
+                            format!("register names are one letter A-Z, not `{name}`"),
+                        ))
+                    }
+                };
+                let value = value.parse::<usize>().map_err(|e| {
+                    ParseError::new(line_no, format!("register {name} value `{value}`: {e}"))
+                })?;
+
+                if index >= registers.len() {
+                    registers.resize(index + 1, None);
+                }
+                if registers[index].replace(value).is_some() {
+                    return Err(ParseError::new(
+                        line_no,
+                        format!("register {name} is given twice"),
+                    ));
+                }
+            }
+            ["Program"] => {
+                if let Some((first, _)) = program {
+                    return Err(ParseError::new(
+                        line_no,
+                        format!("a second program, after the one on line {first}"),
+                    ));
+                }
+
+                let codes = value
+                    .split(',')
+                    .enumerate()
+                    .map(|(k, num)| match num.trim().parse::<u8>() {
+                        Ok(x) if x < 8 => Ok(x),
+                        _ => {
+                            let kind = if k % 2 == 0 { "opcode" } else { "operand" };
+                            Err(ParseError::new(
+                                line_no,
+                                format!("{kind} #{k} `{}` isn't in 0-7", num.trim()),
+                            ))
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if codes.len() % 2 != 0 {
+                    return Err(ParseError::new(
+                        line_no,
+                        format!(
+                            "{} numbers can't all pair up as opcode, operand",
+                            codes.len()
+                        ),
+                    ));
+                }
+
+                // adv, bst, out, bdv and cdv read a combo operand, where 7 is reserved
+                let reserved = codes
+                    .chunks(2)
+                    .position(|pair| matches!(pair, [0 | 2 | 5 | 6 | 7, 7]));
+                if let Some(i) = reserved {
+                    return Err(ParseError::new(
+                        line_no,
+                        format!(
+                            "operand #{} is the reserved combo operand 7 (opcode {})",
+                            2 * i + 1,
+                            codes[2 * i]
+                        ),
+                    ));
+                }
+
+                program = Some((line_no, codes));
+            }
+            _ => {
+                return Err(ParseError::new(
+                    line_no,
+                    format!(
+                        "expected `Register X` or `Program`, got `{}`",
+                        key.join(" ")
+                    ),
+                ))
+            }
+        }
+    }
+
+    let (_, codes) = program
+        .ok_or_else(|| ParseError::new(input.lines().count() + 1, "missing the `Program:` line"))?;
+
+    let quine_tgt = codes
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let instructions = codes.into_iter().to_instr_iter().collect::<Vec<_>>();
+    let registers = registers
+        .into_iter()
+        .map(|value| From::from(value.unwrap_or(0)))
+        .collect();
+
+    Ok((Vm::new(instructions, registers), quine_tgt))
 }
 
 pub(crate) type Units = String;
 
-pub(crate) fn handle_puzzle1(input: &str) -> Units {
-    let (mut vm, _) = parse::<usize>(input);
+pub(crate) fn handle_puzzle1(input: &str) -> Result<Units, ParseError> {
+    let (mut vm, _) = parse::<usize>(input)?;
 
     while vm.exec() {}
 
-    vm.output
+    Ok(vm
+        .output
         .into_iter()
         .map(|k| k.to_string())
         .collect::<Vec<String>>()
-        .join(",")
+        .join(","))
 }
 
 #[test]
@@ -248,7 +377,56 @@ Register C: 0
 
 Program: 0,1,5,4,3,0"#;
 
-    assert_eq!(handle_puzzle1(input), "4,6,3,5,6,3,5,2,1,0");
+    assert_eq!(handle_puzzle1(input)?, "4,6,3,5,6,3,5,2,1,0");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse() -> Result<(), Box<dyn std::error::Error>> {
+    let input = "\r\n  Register C:   3 \r\nRegister  A: 729\r\n\r\nRegister D: 4\r\n\r\nProgram: 0, 1,5,4 ,3,0\r\n";
+
+    let (vm, quine_tgt) = parse::<usize>(input)?;
+
+    assert_eq!(vm.registers, vec![729, 0, 3, 4]);
+    assert_eq!(quine_tgt, "0,1,5,4,3,0");
+    assert_eq!(vm.instructions.len(), 3);
+
+    let error = |input: &str| parse::<usize>(input).err().map(|e| e.to_string());
+
+    assert_eq!(
+        error("Register A: 1\nRegister A: 2\n\nProgram: 0,3"),
+        Some("line 2: register A is given twice".to_string())
+    );
+    assert_eq!(
+        error("Register A: 1\n\nProgram: 0,3,8,4"),
+        Some("line 3: opcode #2 `8` isn't in 0-7".to_string())
+    );
+    assert_eq!(
+        error("Register A: 1\n\nProgram: 0,3,5"),
+        Some("line 3: 3 numbers can't all pair up as opcode, operand".to_string())
+    );
+    assert_eq!(
+        error("Register A: 1\n\nProgram: 1,7,5,7"),
+        Some("line 3: operand #3 is the reserved combo operand 7 (opcode 5)".to_string())
+    );
+    assert_eq!(
+        error("Register AB: 1\n\nProgram: 0,3"),
+        Some("line 1: register names are one letter A-Z, not `AB`".to_string())
+    );
+    assert_eq!(
+        error("Register A: -1\n\nProgram: 0,3")
+            .map(|e| e.starts_with("line 1: register A value `-1`")),
+        Some(true)
+    );
+    assert_eq!(
+        error("Register A: 1\nRegister B: 0"),
+        Some("line 3: missing the `Program:` line".to_string())
+    );
+    assert_eq!(
+        error("Register A: 1\nRegistr B: 0\n\nProgram: 0,3"),
+        Some("line 2: expected `Register X` or `Program`, got `Registr B`".to_string())
+    );
 
     Ok(())
 }
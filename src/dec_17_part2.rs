@@ -1,22 +1,104 @@
-use core::panic;
-use std::ops::{BitXor, BitXorAssign};
+use std::collections::HashMap;
+use std::ops::BitXorAssign;
+use std::rc::Rc;
 
-use crate::dec_17_part1::{parse, Vm};
+use crate::dec_17_part1::{parse, ParseError, PowerOfTwo, REG_A};
 use itertools::Itertools;
 
 // R: From<u8>
 // + Clone
 // + std::ops::BitXor<R, Output = R>
-// + std::ops::Rem<usize, Output = R>
+// + std::ops::Rem<PowerOfTwo, Output = R>
 // + std::ops::Shr<Output = R>
 // + std::ops::BitXorAssign<R>
 // + std::cmp::PartialEq<usize>,
 
+/// A little-endian vector of symbolic bits; `state[0]` is the least
+/// significant bit. Bits past the end of `state` are implicitly zero.
 #[derive(Clone)]
 struct VBit {
     state: Vec<Bit>,
 }
 
+impl VBit {
+    /// A fully symbolic register of `width` bits named `{prefix}0..{prefix}{width-1}`
+    pub fn symbolic(prefix: &str, width: usize) -> Self {
+        VBit {
+            state: (0..width)
+                .map(|i| Bit::header(format!("{prefix}{i}")))
+                .collect(),
+        }
+    }
+
+    fn bit(&self, i: usize) -> Bit {
+        self.state.get(i).cloned().unwrap_or(Bit::Const(0))
+    }
+
+    /// the value of the vector if every bit is known, otherwise `None`
+    pub fn as_const(&self) -> Option<usize> {
+        self.state
+            .iter()
+            .enumerate()
+            .try_fold(0_usize, |out, (i, bit)| match bit {
+                Bit::Const(0) => Some(out),
+                Bit::Const(_) if i >= usize::BITS as usize => None,
+                Bit::Const(_) => Some(out | (1 << i)),
+                _ => None,
+            })
+    }
+
+    /// the bits that aren't trailing constant zeros
+    fn significant(&self) -> &[Bit] {
+        let len = self
+            .state
+            .iter()
+            .rposition(|bit| !matches!(bit, Bit::Const(0)))
+            .map_or(0, |i| i + 1);
+
+        &self.state[..len]
+    }
+
+    /// A symbolic shift by `rhs`: bit `i` of the result is the xor over every
+    /// possible value `k` of `[rhs == k] & self[i + k]`. The selectors are
+    /// mutually exclusive, so xor stands in for or.
+    fn multiplex(&self, rhs: &VBit) -> VBit {
+        // shifting by 64 or more clears a 64 bit register, so only the low
+        // six bits of the amount select anything, and only while the rest are 0
+        let selector = rhs.significant();
+        let (low, high) = selector.split_at(selector.len().min(6));
+        let high_clear = high
+            .iter()
+            .fold(Bit::Const(1), |acc, bit| acc.and(&bit.not()));
+
+        let cases = (0..1_usize << low.len())
+            .map(|k| {
+                low.iter()
+                    .enumerate()
+                    .fold(high_clear.clone(), |acc, (j, bit)| {
+                        if (k >> j) & 1 == 1 {
+                            acc.and(bit)
+                        } else {
+                            acc.and(&bit.not())
+                        }
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        VBit {
+            state: (0..self.state.len())
+                .map(|i| {
+                    cases
+                        .iter()
+                        .enumerate()
+                        .fold(Bit::Const(0), |acc, (k, case)| {
+                            acc.xor(&case.and(&self.bit(i + k)))
+                        })
+                })
+                .collect(),
+        }
+    }
+}
+
 impl From<u8> for VBit {
     fn from(mut value: u8) -> Self {
         let mut state = vec![];
@@ -29,6 +111,18 @@ impl From<u8> for VBit {
     }
 }
 
+impl From<usize> for VBit {
+    fn from(mut value: usize) -> Self {
+        let mut state = vec![];
+        while value > 0 {
+            state.push(Bit::Const((value & 1) as u8));
+            value >>= 1;
+        }
+
+        VBit { state }
+    }
+}
+
 impl std::ops::BitXor<VBit> for VBit {
     type Output = VBit;
 
@@ -38,469 +132,505 @@ impl std::ops::BitXor<VBit> for VBit {
     }
 }
 
-impl std::ops::Rem<usize> for VBit {
+impl std::ops::Rem<PowerOfTwo> for VBit {
     type Output = VBit;
 
-    fn rem(self, rhs: usize) -> Self::Output {
-        assert_eq!(rhs, 8); // only circumstance where it's necessary
-
+    fn rem(self, rhs: PowerOfTwo) -> Self::Output {
         Self::Output {
-            state: self.state.into_iter().take(3).collect::<Vec<_>>(),
+            state: (0..rhs.bits()).map(|i| self.bit(i)).collect(),
         }
     }
 }
 
-impl Into<usize> for VBit {
-    fn into(self) -> usize {
-        let mut out = 0;
-
-        for bit in self.state {
-            match bit {
-                Bit::Named(_) => panic!("haha fuck it happened"),
-                Bit::Const(x) => out |= x as usize,
-                Bit::Ident => panic!("and again it happened loool"),
-                Bit::Invert => panic!("why am I calling inverts!?"),
-                Bit::Xor(_, _) => panic!("shit fuck, xors?!"),
-            }
-
-            out <<= 1;
-        }
-
-        out
-    }
-}
-
 impl std::ops::Shr for VBit {
     type Output = VBit;
 
+    /// Constant shift amounts just drop bits; symbolic ones go through
+    /// `multiplex`
     fn shr(self, rhs: Self) -> Self::Output {
-        Self::Output {
-            state: self.state.into_iter().skip(rhs.into()).collect(),
+        match rhs.as_const() {
+            Some(amount) => Self::Output {
+                state: self.state.into_iter().skip(amount).collect(),
+            },
+            None => self.multiplex(&rhs),
         }
     }
 }
 
 impl std::ops::BitXorAssign<VBit> for VBit {
     fn bitxor_assign(&mut self, rhs: VBit) {
-        for i in 0..(self.state.len().min(rhs.state.len())) {
-            let bit_self = &self.state[i];
-            let bit_rhs = &rhs.state[i];
-            self.state[i] = bit_self.xor(&bit_rhs);
-        }
+        let width = self.state.len().max(rhs.state.len());
+        self.state = (0..width).map(|i| self.bit(i).xor(&rhs.bit(i))).collect();
     }
 }
 
 impl std::cmp::PartialEq<usize> for VBit {
+    /// Symbolic bits are assumed to make the comparison unequal, which is
+    /// the only way the loop in a day 17 program keeps going. Since the
+    /// register shrinks by every constant shift, a symbolic `A` of
+    /// `3 * n` bits runs the loop exactly `n` times; the solver has to
+    /// separately require that its top bits aren't all zero.
     fn eq(&self, other: &usize) -> bool {
-        if *other == 0 {
-            for b in &self.state {
-                match b {
-                    Bit::Const(x) => {
-                        if *x == 1 {
-                            return false;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        } else {
-            panic!();
+        match self.as_const() {
+            Some(value) => value == *other,
+            None => false,
         }
-
-        return true;
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 enum Bit {
     Named(String),
     Const(u8),
-    Ident,
-    Invert,
-    Xor(Box<Bit>, Box<Bit>),
+    Xor(Rc<Bit>, Rc<Bit>),
+    And(Rc<Bit>, Rc<Bit>),
 }
 
 impl Bit {
     pub fn header(name: String) -> Self {
         Self::Named(name)
     }
-    pub fn unknown() -> Self {
-        Self::Ident
-    }
     pub fn constant(value: u8) -> Self {
         Self::Const(value)
     }
     pub fn xor(&self, other: &Self) -> Self {
-        Self::Xor(Box::new(self.clone()), Box::new(other.clone()))
+        match (self, other) {
+            (Bit::Const(a), Bit::Const(b)) => Bit::Const(a ^ b),
+            (Bit::Const(0), x) | (x, Bit::Const(0)) => x.clone(),
+            _ => Self::Xor(Rc::new(self.clone()), Rc::new(other.clone())),
+        }
+    }
+    pub fn and(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Bit::Const(a), Bit::Const(b)) => Bit::Const(a & b),
+            (Bit::Const(0), _) | (_, Bit::Const(0)) => Bit::Const(0),
+            (Bit::Const(1), x) | (x, Bit::Const(1)) => x.clone(),
+            _ => Self::And(Rc::new(self.clone()), Rc::new(other.clone())),
+        }
+    }
+    pub fn not(&self) -> Self {
+        self.xor(&Bit::Const(1))
+    }
+
+    /// evaluate under a partial assignment; `None` if it depends on an
+    /// unassigned bit
+    pub fn eval(&self, space: &Space) -> Option<u8> {
+        match self {
+            Bit::Named(name) => space.get(name).map(u8::from),
+            Bit::Const(x) => Some(*x),
+            Bit::Xor(a, b) => Some(a.eval(space)? ^ b.eval(space)?),
+            Bit::And(a, b) => match (a.eval(space), b.eval(space)) {
+                (Some(0), _) | (_, Some(0)) => Some(0),
+                (Some(a), Some(b)) => Some(a & b),
+                _ => None,
+            },
+        }
     }
 
     pub fn set(&mut self, value: u8) {
         *self = Bit::Const(value);
     }
 
-    pub fn try_name(&self) -> Result<&String, &Self> {
-        if let Bit::Named(name) = self {
-            Ok(name)
-        } else {
-            Err(self)
+    /// the operands of a chain of xors, e.g. `a ⊕ (b ⊕ c)` => `[a, b, c]`
+    fn xor_terms(&self) -> Vec<&Bit> {
+        match self {
+            Bit::Xor(a, b) => [a.xor_terms(), b.xor_terms()].concat(),
+            _ => vec![self],
+        }
+    }
+
+    /// the operands of a chain of ands, e.g. `(a ∧ b) ∧ c` => `[a, b, c]`
+    fn and_terms(&self) -> Vec<&Bit> {
+        match self {
+            Bit::And(a, b) => [a.and_terms(), b.and_terms()].concat(),
+            _ => vec![self],
+        }
+    }
+
+    /// a named bit, possibly negated
+    fn is_literal(&self) -> bool {
+        let terms = self.xor_terms();
+        let named = terms.iter().filter(|t| matches!(t, Bit::Named(_))).count();
+
+        named == 1
+            && terms
+                .iter()
+                .all(|t| matches!(t, Bit::Named(_) | Bit::Const(_)))
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Bit::Const(_) => 0,
+            Bit::Named(_) => 1,
+            Bit::And(_, _) => 2,
+            Bit::Xor(_, _) => 3,
         }
     }
 }
 
-impl ToString for Bit {
-    fn to_string(&self) -> String {
+/// names compare by their prefix then their index, so `a2` comes before `a10`
+fn natural_key(name: &str) -> (&str, Option<usize>, &str) {
+    let split = name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (prefix, index) = name.split_at(split);
+
+    (prefix, index.parse().ok(), name)
+}
+
+impl Ord for Bit {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (Bit::Const(a), Bit::Const(b)) => a.cmp(b),
+            (Bit::Named(a), Bit::Named(b)) => natural_key(a).cmp(&natural_key(b)),
+            (Bit::Xor(a1, b1), Bit::Xor(a2, b2)) | (Bit::And(a1, b1), Bit::And(a2, b2)) => {
+                (a1, b1).cmp(&(a2, b2))
+            }
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Bit {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::fmt::Display for Bit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Bit::Named(h) => h.to_string(),
-            Bit::Const(x) => x.to_string(),
-            Bit::Ident => String::from("="),
-            Bit::Invert => String::from("~"),
-            Bit::Xor(a, b) => {
-                match (a.try_name(), b.try_name()) {
-                    (Ok(name_a), Ok(name_b)) => return format!("{name_a}⊕{name_b}"),
-                    (Err(unnamed), Ok(name_a)) | (Ok(name_a), Err(unnamed)) => {
-                        return match unnamed.to_string().as_str() {
-                            "=" | "0" => name_a.to_string(),
-                            "~" | "1" => format!("~{name_a}"),
-                            _ => unreachable!(),
-                        };
-                    }
-                    _ => {
-                        let a = a.to_string();
-                        let b = b.to_string();
-
-                        // 0, 1, =, ~
-                        match (a.as_str(), b.as_str()) {
-                            ("~", "=")
-                            | ("=", "~")
-                            | ("~", "0")
-                            | ("0", "~")
-                            | ("1", "=")
-                            | ("=", "1") => "~".to_string(),
-                            ("=", "=")
-                            | ("~", "~")
-                            | ("~", "1")
-                            | ("1", "~")
-                            | ("=", "0")
-                            | ("0", "=") => "=".to_string(),
-                            ("1", "0") | ("0", "1") => "1".to_string(),
-                            ("1", "1") | ("0", "0") => "0".to_string(),
-                            ("~", x) | (x, "~") | ("1", x) | (x, "1") => {
-                                if x.starts_with("~") {
-                                    x.chars().skip(1).collect::<String>()
-                                } else {
-                                    "~".to_string() + x
-                                }
-                            }
-                            ("=", x) | (x, "=") | ("0", x) | (x, "0") => x.to_string(),
-                            _ => unreachable!(),
-                        }
-                    }
+            Bit::Named(h) => write!(f, "{h}"),
+            Bit::Const(x) => write!(f, "{x}"),
+            Bit::Xor(_, _) => {
+                let mut terms = self.xor_terms();
+                let negated = terms.iter().filter(|t| **t == &Bit::Const(1)).count() % 2 == 1;
+                terms.retain(|t| !matches!(t, Bit::Const(_)));
+
+                let chain = terms.iter().join("⊕");
+                match (negated, terms.as_slice()) {
+                    (false, _) => write!(f, "{chain}"),
+                    (true, [Bit::Named(_)]) => write!(f, "~{chain}"),
+                    (true, _) => write!(f, "~({chain})"),
                 }
             }
+            Bit::And(_, _) => {
+                let factors = self
+                    .and_terms()
+                    .into_iter()
+                    .map(|t| match t {
+                        Bit::Xor(_, _) if !t.is_literal() => format!("({t})"),
+                        _ => t.to_string(),
+                    })
+                    .join("∧");
+
+                write!(f, "{factors}")
+            }
         }
     }
 }
 
-/// A virtual superposition with 64 bits
-#[derive(Clone)]
-struct Space {
-    state: [Option<bool>; 64],
+/// Rewrites `Bit` trees into a canonical form: xor and and chains are
+/// flattened, constants folded, `x ⊕ x`, `x ∧ x` and `x ∧ ~x` cancelled, and
+/// the remaining operands sorted. Every node it hands back is interned, so
+/// equal subterms share one allocation across all the trees it has seen.
+#[derive(Default)]
+struct Simplifier {
+    interned: HashMap<Bit, Rc<Bit>>,
 }
 
-impl Space {
+impl Simplifier {
     pub fn new() -> Self {
-        Self { state: [None; 64] }
+        Self::default()
     }
 
-    pub fn set(&mut self, constraint: &str, value: bool) -> bool {
-        if let Some((negate, index)) = constraint.split_once("a") {
-            let negate = negate == "~";
-            let index = index.parse::<usize>().unwrap();
-            if let Some(inbounds_ref) = self.state.get_mut(index) {
-                let value = value ^ negate;
-                if let Some(current_constraint) = inbounds_ref {
-                    // if already in use, it must match our additional constraint
-                    *current_constraint == value
-                } else {
-                    // set the value
-                    *inbounds_ref = Some(value);
-                    true
-                }
-            } else {
-                panic!();
+    fn share(&mut self, bit: Bit) -> Rc<Bit> {
+        self.interned
+            .entry(bit)
+            .or_insert_with_key(|bit| Rc::new(bit.clone()))
+            .clone()
+    }
+
+    pub fn simplify(&mut self, bit: &Bit) -> Rc<Bit> {
+        match bit {
+            Bit::Named(_) | Bit::Const(_) => self.share(bit.clone()),
+            Bit::Xor(a, b) => {
+                let a = self.simplify(a);
+                let b = self.simplify(b);
+                let terms = [a.xor_terms(), b.xor_terms()]
+                    .concat()
+                    .into_iter()
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                self.xor_chain(terms)
+            }
+            Bit::And(a, b) => {
+                let a = self.simplify(a);
+                let b = self.simplify(b);
+                let factors = [a.and_terms(), b.and_terms()]
+                    .concat()
+                    .into_iter()
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                self.and_chain(factors)
             }
-        } else {
-            true
         }
     }
 
-    pub fn unset(&mut self, constraint: &str) {
-        if let Some((_, index)) = constraint.split_once("a") {
-            let index = index.parse::<usize>().unwrap();
-            if let Some(inbounds_ref) = self.state.get_mut(index) {
-                *inbounds_ref = None;
+    /// `terms` must already be simplified
+    fn xor_chain(&mut self, mut terms: Vec<Bit>) -> Rc<Bit> {
+        let parity = terms
+            .iter()
+            .filter_map(|t| match t {
+                Bit::Const(x) => Some(*x),
+                _ => None,
+            })
+            .fold(0, |acc, x| acc ^ x);
+        terms.retain(|t| !matches!(t, Bit::Const(_)));
+        terms.sort();
+
+        // x ⊕ x = 0, so after sorting equal terms cancel in adjacent pairs
+        let mut kept: Vec<Bit> = vec![];
+        for term in terms {
+            if kept.last() == Some(&term) {
+                kept.pop();
             } else {
-                panic!();
+                kept.push(term);
             }
         }
-    }
-
-    pub fn finalized(&self) -> usize {
-        assert!(self.state.len() <= 64);
+        if parity == 1 {
+            kept.insert(0, Bit::Const(1));
+        }
 
-        let mut out = 0_usize;
+        self.chain(kept, Bit::Const(0), Bit::Xor)
+    }
 
-        for bit in &self.state {
-            if let Some(true) = bit {
-                out |= 1;
-            }
-            out <<= 1;
+    /// `factors` must already be simplified
+    fn and_chain(&mut self, mut factors: Vec<Bit>) -> Rc<Bit> {
+        if factors.contains(&Bit::Const(0)) {
+            return self.share(Bit::Const(0));
+        }
+        factors.retain(|t| !matches!(t, Bit::Const(_)));
+        factors.sort();
+        factors.dedup();
+
+        let contradiction = factors.iter().any(|t| {
+            let complement = [t.xor_terms(), vec![&Bit::Const(1)]].concat();
+            let complement = complement.into_iter().cloned().collect::<Vec<_>>();
+            let complement = self.xor_chain(complement);
+
+            factors.binary_search(&complement).is_ok()
+        });
+        if contradiction {
+            return self.share(Bit::Const(0));
         }
 
-        out
+        self.chain(factors, Bit::Const(1), Bit::And)
     }
-}
-
-pub(crate) fn handle_puzzle2(input: &str) -> Option<usize> {
-    let targets = Vec::<usize>::from([2, 4, 1, 1, 7, 5, 4, 6, 0, 3, 1, 4, 5, 5, 3, 0]);
-    // UNCOMMENT BELOW to visualize what the fuck I'm even doing in `get_constraints`
-    // for (k, target) in targets.iter().enumerate() {
-    //     print_table_console(k, target.clone());
-    // }
 
-    // let (mut vm, _) = parse::<VBit>(input);
-    // vm.registers[0] = VBit {
-    //     state: (0..64).map(|i| Bit::header(format!("a{}", i))).collect(),
-    // };
+    /// right-nests sorted operands as `t0 op (t1 op (... op tn))`
+    fn chain(&mut self, terms: Vec<Bit>, empty: Bit, op: fn(Rc<Bit>, Rc<Bit>) -> Bit) -> Rc<Bit> {
+        let mut terms = terms.into_iter().rev();
+        let Some(last) = terms.next() else {
+            return self.share(empty);
+        };
+
+        let last = self.share(last);
+        terms.fold(last, |acc, term| {
+            let term = self.share(term);
+            self.share(op(term, acc))
+        })
+    }
+}
 
-    // while vm.exec() {}
+/// A virtual superposition with 64 bits
+#[derive(Clone)]
+struct Space {
+    state: [Option<bool>; 64],
+}
 
-    // let mut space = Space::new();
+impl Space {
+    pub fn new() -> Self {
+        Self { state: [None; 64] }
+    }
 
-    // let constraints: Constraints = todo!();
+    fn index(name: &str) -> usize {
+        name.strip_prefix("a")
+            .and_then(|index| index.parse::<usize>().ok())
+            .filter(|index| *index < 64)
+            .unwrap_or_else(|| panic!("{name} is not a bit of register A"))
+    }
 
-    // let first_discovered = solve(&mut space, &targets, &constraints, 0);
+    pub fn get(&self, name: &str) -> Option<bool> {
+        self.state[Space::index(name)]
+    }
 
-    // first_discovered.unwrap()
+    pub fn set(&mut self, name: &str, value: bool) {
+        self.state[Space::index(name)] = Some(value);
+    }
 
-    let (mut vm, _) = parse::<usize>(input);
+    pub fn unset(&mut self, name: &str) {
+        self.state[Space::index(name)] = None;
+    }
 
-    let mut a_start = 0b000_000_000_000____000_000_000_000____000_000_000_000____000_000_000_000;
-    let mut incr = 15;
-    let mut max_score = 0;
+    pub fn finalized(&self) -> usize {
+        self.state
+            .iter()
+            .enumerate()
+            .filter(|(_, bit)| **bit == Some(true))
+            .fold(0, |out, (i, _)| out | (1 << i))
+    }
+}
 
-    loop {
-        vm.reset();
-        vm.registers[0] = a_start;
+pub(crate) fn handle_puzzle2(input: &str) -> Result<Option<usize>, ParseError> {
+    let (mut vm, quine_tgt) = parse::<VBit>(input)?;
+    let targets = quine_tgt
+        .split(",")
+        .map(|num| num.parse::<usize>().unwrap())
+        .collect::<Vec<_>>();
 
-        while vm.exec() {}
+    // every iteration shifts 3 bits out of A, so it takes exactly that many
+    // bits to print the whole program
+    let width = 3 * targets.len();
+    vm.registers[REG_A] = VBit::symbolic("a", width);
 
-        let score = vm
-            .output
-            .iter()
-            .rev()
-            .zip(targets.iter().rev())
-            .take_while(|(a, b)| a == b)
-            .count();
-
-        if score == 16 {
-            return Some(a_start);
-        } else if score > max_score {
-            incr = 15 - score;
-            max_score = score;
-        }
+    while vm.exec() {}
 
-        a_start += 1 << (3 * incr);
-    }
+    let Some(constraints) = get_constraints(&vm.output, &targets, width) else {
+        return Ok(None);
+    };
+    let order = (0..width)
+        .rev()
+        .map(|i| format!("a{i}"))
+        .collect::<Vec<_>>();
+    let mut space = Space::new();
 
-    // Idk, I quit
+    Ok(solve(&mut space, &order, &constraints))
 }
 
-type Constraints = Vec<Vec<Vec<Bit>>>;
-
-/// a recursive backtracking solution to chunk through the constraint sets
-/// over the bits of register A, s.t. it produces a quine with the VM.
-fn solve(
-    space: &mut Space,
-    targets: &[usize],
-    constraints: &Constraints,
-    depth: usize,
-) -> Option<usize> {
-    if depth >= targets.len() {
-        return Some(space.finalized());
-    }
-
-    let mut min_solution: Option<usize> = None;
+/// each symbolic bit paired with the value it's required to take
+type Constraints = Vec<(Bit, u8)>;
 
-    let target = targets[depth];
+/// a recursive backtracking solution to chunk through the bits of register A,
+/// s.t. it produces a quine with the VM. Bits are assigned from the most
+/// significant down with 0 tried first, so the first solution is the smallest.
+fn solve(space: &mut Space, order: &[String], constraints: &Constraints) -> Option<usize> {
+    let violated = constraints
+        .iter()
+        .any(|(bit, tgt)| bit.eval(space).is_some_and(|value| value != *tgt));
+    if violated {
+        return None;
+    }
 
-    for constraint in &constraints[depth] {
-        for (i, cstr) in constraint.iter().enumerate() {
-            let tgt_bit = if (target >> i) & 1 == 1 { true } else { false };
-            if !space.set(cstr.to_string().as_str(), tgt_bit) {
-                return min_solution;
-            }
-        }
-        if let Some(x) = solve(space, targets, constraints, depth + 1) {
-            min_solution = min_solution.map_or(Some(x), |m| Some(m.min(x)));
-        }
-        for cstr in constraint {
-            space.unset(cstr.to_string().as_str());
+    let Some((name, rest)) = order.split_first() else {
+        return Some(space.finalized());
+    };
+
+    let mut solution = None;
+    for value in [false, true] {
+        space.set(name, value);
+        solution = solve(space, rest, constraints);
+        if solution.is_some() {
+            break;
         }
     }
+    space.unset(name);
 
-    min_solution
+    solution
 }
 
-fn get_constraints(targets: &Vec<usize>) -> Vec<Vec<Vec<Bit>>> {
-    targets
-        .iter()
-        .enumerate()
-        .map(|(k, _tgt)| {
-            let a = (0 + (3 * k)..8 + (3 * k))
-                .map(|x| Bit::header(format!("a{}", x)))
-                .collect::<Vec<_>>();
-
-            (0..8)
-                .map(|a_mod_8| {
-                    let mut a2 = a.clone();
+/// pin every output bit of the symbolic run to the matching bit of its target,
+/// and require A's top 3 bits not be all 0, otherwise the program would halt
+/// an iteration early
+fn get_constraints(output: &[VBit], targets: &[usize], width: usize) -> Option<Constraints> {
+    if output.len() != targets.len() || width < 3 {
+        return None;
+    }
 
-                    a2[0].set((a_mod_8 ^ 1) & 1);
-                    a2[1].set(((a_mod_8 ^ 1) >> 1) & 1);
-                    a2[2].set(((a_mod_8 ^ 1) >> 2) & 1);
+    let mut constraints = output
+        .iter()
+        .zip(targets)
+        .flat_map(|(out, tgt)| (0..3).map(move |i| (out.bit(i), ((tgt >> i) & 1) as u8)))
+        .collect::<Constraints>();
 
-                    let a2 = a2.iter().take(3).map(|b| b.clone()).collect::<Vec<_>>();
+    let top = VBit::symbolic("a", width).state.split_off(width - 3);
+    let top_clear = top
+        .iter()
+        .fold(Bit::Const(1), |acc, bit| acc.and(&bit.not()));
+    constraints.push((top_clear, 0));
+
+    let mut simplifier = Simplifier::new();
+    let mut simplified = Constraints::new();
+    for (bit, tgt) in constraints {
+        match simplifier.simplify(&bit).as_ref() {
+            Bit::Const(x) if *x == tgt => {}
+            Bit::Const(_) => return None,
+            bit => simplified.push((bit.clone(), tgt)),
+        }
+    }
 
-                    let mut a3 = a.clone();
+    Some(simplified)
+}
 
-                    a3[0].set(a_mod_8 & 1);
-                    a3[1].set((a_mod_8 >> 1) & 1);
-                    a3[2].set((a_mod_8 >> 2) & 1);
+#[test]
+fn test_puzzle2() -> Result<(), Box<dyn std::error::Error>> {
+    let input = r#"Register A: 2024
+Register B: 0
+Register C: 0
 
-                    let a3 = a3
-                        .iter()
-                        .skip((a_mod_8 ^ 1) as usize)
-                        .map(|bit| bit.clone())
-                        .take(3)
-                        .collect::<Vec<_>>();
+Program: 0,3,5,4,3,0"#;
 
-                    a2.iter()
-                        .zip(a3.iter())
-                        .enumerate()
-                        .map(|(i, (a2, a3))| {
-                            let mut x = a2.xor(a3);
-                            if i == 2 {
-                                x = x.xor(&Bit::constant(1));
-                            }
+    assert_eq!(handle_puzzle2(input)?, Some(117440));
 
-                            x
-                        })
-                        .collect()
-                })
-                .collect()
-        })
-        .collect::<Vec<Vec<_>>>()
+    Ok(())
 }
 
-fn print_table_console(k: usize, target: usize) {
-    let ao = (0 + (3 * k)..8 + (3 * k))
-        .rev()
-        .map(|x| Bit::header(format!("a{}", x)))
-        .collect::<Vec<_>>();
-    let bo = (0 + (3 * k)..8 + (3 * k))
-        .rev()
-        .map(|x| Bit::header(format!("b{}", x)))
-        .collect::<Vec<_>>();
+#[test]
+fn test_symbolic_matches_concrete() -> Result<(), Box<dyn std::error::Error>> {
+    let input = r#"Register A: 0
+Register B: 0
+Register C: 0
 
-    let mut a = Vec::<Vec<Bit>>::new();
-    for i in 0..8 {
-        let mut aa = ao.clone();
-        for i in 0..5 {
-            aa[i].set(0);
-        }
+Program: 2,4,1,1,7,5,4,6,0,3,1,4,5,5,3,0"#;
 
-        aa[5].set((i >> 2) & 1);
-        aa[6].set((i >> 1) & 1);
-        aa[7].set(i & 1);
+    let a = handle_puzzle2(input)?.unwrap();
 
-        a.push(aa);
-    }
+    let (mut vm, quine_tgt) = parse::<usize>(input)?;
+    vm.registers[REG_A] = a;
+    while vm.exec() {}
 
-    let mut a2 = Vec::<Vec<Bit>>::new();
-    for i in 0..8 {
-        let mut aa = ao.clone();
-        for i in 0..5 {
-            aa[i].set(0);
-        }
+    assert_eq!(vm.output.iter().join(","), quine_tgt);
 
-        aa[5].set(((i ^ 1) >> 2) & 1);
-        aa[6].set(((i ^ 1) >> 1) & 1);
-        aa[7].set((i ^ 1) & 1);
-        a2.push(aa);
-    }
-
-    let mut a3 = Vec::<Vec<Bit>>::new();
-    for i in 0..8_usize {
-        let mut aa = ao.clone();
-
-        aa[5].set(((i >> 2) & 1) as u8);
-        aa[6].set(((i >> 1) & 1) as u8);
-        aa[7].set((i & 1) as u8);
-
-        let rlo = 0..(i ^ 1);
-        let rhi = 0..8 - (i ^ 1);
-        let aa = rlo
-            .rev()
-            .into_iter()
-            .map(|e| Bit::Named(format!("a{}", 8 + e)))
-            .chain(aa[rhi].iter().map(|e| e.clone()))
-            .collect();
-        a3.push(aa);
-    }
-
-    println!(
-        "Input #{:>2}, target = {}{}{}",
-        k,
-        (target >> 2) & 1,
-        (target >> 1) & 1,
-        target & 1
-    );
-    println!(
-        "+{:-^37}+{:-^37}+{:-^37}+{:-^133}+",
-        " A ", " A2 := A mod 8 ⊕ 1 ", " A3 := A >> (A mod 8 ⊕ 1) ", " A2 ⊕ A3 "
-    );
-    let headers = ao.iter().map(|b| format!("{:>3}", b.to_string())).join(" ");
-    let headers2 = bo
-        .iter()
-        .map(|b| format!("{:>15}", b.to_string()))
-        .join(" ");
-    println!(
-        "| ... {} | ... {} | ... {} | ... {}",
-        headers, headers, headers, headers2
+    Ok(())
+}
+
+#[test]
+fn test_simplify() -> Result<(), Box<dyn std::error::Error>> {
+    let a = |i: usize| Bit::header(format!("a{i}"));
+    let mut simplifier = Simplifier::new();
+    let mut simplify = |bit: Bit| simplifier.simplify(&bit).to_string();
+
+    // constants fold, and double negation cancels
+    assert_eq!(simplify(a(1).xor(&Bit::constant(0))), "a1");
+    assert_eq!(simplify(a(1).not().not()), "a1");
+    assert_eq!(simplify(a(1).not().xor(&a(2))), "~(a1⊕a2)");
+
+    // x ⊕ x = 0, regardless of where the pair sits in the chain
+    assert_eq!(simplify(a(10).xor(&a(2)).xor(&a(10)).xor(&a(3))), "a2⊕a3");
+    assert_eq!(simplify(a(4).xor(&a(5)).xor(&a(5).xor(&a(4)))), "0");
+
+    // ands dedupe, contradict, and sort naturally
+    assert_eq!(simplify(a(10).and(&a(2)).and(&a(10))), "a2∧a10");
+    assert_eq!(simplify(a(7).and(&a(7).not())), "0");
+    assert_eq!(
+        simplify(a(1).xor(&a(2)).and(&a(3).not()).xor(&a(0))),
+        "a0⊕~a3∧(a1⊕a2)"
     );
-    println!("+{:-^37}+{:-^37}+{:-^37}+{:-^133}+", "", "", "", "");
-    for ((a, b), c) in a.into_iter().zip(a2.into_iter()).zip(a3) {
-        let aout = a.iter().map(|b| format!("{:>3}", b.to_string())).join(" ");
-        let aout = format!("... {}", aout);
-        let bout = b.iter().map(|b| format!("{:>3}", b.to_string())).join(" ");
-        let bout = format!("... {}", bout);
-        let cout = c.iter().map(|b| format!("{:>3}", b.to_string())).join(" ");
-        let cout = format!("... {}", cout);
-        let a2_xor_a3 = b
-            .iter()
-            .zip(c.iter())
-            .enumerate()
-            .map(|(i, (b, c))| {
-                let mut x = b.xor(c);
-                if i == 5 {
-                    x = x.xor(&Bit::constant(1));
-                }
 
-                format!("{:>15}", x.to_string())
-            })
-            .join(" ");
-        let bxorc = format!("... {}", a2_xor_a3);
-        println!("| {} | {} | {} | {} |", aout, bout, cout, bxorc);
-    }
-    println!();
+    // reordered but equal chains come back as the same allocation
+    let x = simplifier.simplify(&a(1).xor(&a(2)).and(&a(3)));
+    let y = simplifier.simplify(&a(3).and(&a(2).xor(&a(1))));
+    assert!(Rc::ptr_eq(&x, &y));
+
+    Ok(())
 }
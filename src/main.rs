@@ -7,6 +7,7 @@ mod dec_06;
 mod dec_07;
 mod dec_08;
 mod dec_09;
+mod dec_10;
 
 macro_rules! run_puzzles {
     ($mod_name:ident) => {{
@@ -16,7 +17,46 @@ macro_rules! run_puzzles {
     }};
 }
 
+/// Runs the puzzles of whichever of the days is named `$day`
+macro_rules! run_day {
+    ($day:expr, $($mod_name:ident),*) => {
+        match $day {
+            $(stringify!($mod_name) => run_puzzles!($mod_name),)*
+            day => return Err(format!("unknown day `{day}`").into()),
+        }
+    };
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // `cargo run --bin aoc24 -- <day>` runs just that day's puzzles, and
+    // `<day> <mode>` one of its extra modes instead; `dec_09 <path>` solves a
+    // disk map saved in any of its encodings
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args.as_slice() {
+        [] => {}
+        [day] => {
+            run_day!(
+                *day, dec_01, dec_02, dec_03, dec_04, dec_05, dec_06, dec_07, dec_08, dec_09,
+                dec_10
+            );
+            return Ok(());
+        }
+        ["dec_03", "arithmetic"] => return dec_03::arithmetic(),
+        ["dec_03", "diagnose"] => return dec_03::diagnose(),
+        ["dec_05", "audit"] => return dec_05::audit(),
+        ["dec_06", "animate"] => return dec_06::animate(30),
+        ["dec_06", "animate", fps] => return dec_06::animate(fps.parse()?),
+        ["dec_06", "patrol", turn] => return dec_06::patrol(turn),
+        ["dec_07", "explain", ops] => return dec_07::explain(ops),
+        ["dec_08", "report", puzzle] => return dec_08::report(puzzle),
+        ["dec_09", "compare"] => return dec_09::compare_strategies(),
+        ["dec_09", "step", path] => return dec_09::step_through(path),
+        ["dec_09", path] => return dec_09::run_file(path),
+        ["dec_10", "trails"] => return dec_10::trails(),
+        _ => return Err(format!("unknown mode `{}`", args.join(" ")).into()),
+    }
+
     // run_puzzles!(dec_01);
     // run_puzzles!(dec_02);
     // run_puzzles!(dec_03);
@@ -26,6 +66,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // run_puzzles!(dec_07);
     // run_puzzles!(dec_08);
     run_puzzles!(dec_09);
+    // run_puzzles!(dec_10);
 
     Ok(())
 }